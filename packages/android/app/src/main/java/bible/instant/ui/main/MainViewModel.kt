@@ -116,5 +116,27 @@ class MainViewModel : ViewModel() {
 
         @JvmStatic
         external fun bridgeSearch(q: String): ByteArray
+
+        @JvmStatic
+        external fun bridgeSearchWithOptions(q: String, options: ByteArray): ByteArray
+
+        @JvmStatic
+        external fun bridgeGetVerse(book: Int, chapter: Int, verse: Int): ByteArray
+
+        @JvmStatic
+        external fun bridgeGetChapter(book: Int, chapter: Int): ByteArray
+
+        @JvmStatic
+        external fun bridgeGetRange(
+            startBook: Int,
+            startChapter: Int,
+            startVerse: Int,
+            endBook: Int,
+            endChapter: Int,
+            endVerse: Int
+        ): ByteArray
+
+        @JvmStatic
+        external fun bridgeComplete(q: String, n: Int): ByteArray
     }
 }
@@ -1,12 +1,15 @@
+use engine::proto::data::{Book, VerseKey};
 use engine::proto::engine::decode_index_data;
-use engine::VersearchIndex;
+use engine::proto::service::{decode_search_options, Completions, Response as ServiceResponse};
+use engine::{SearchOptions, VersearchIndex};
 use ffi_support::ByteBuffer as FfiBuffer;
 use jni::objects::{JObject, JString};
-use jni::sys::jbyteArray;
+use jni::sys::{jbyteArray, jint};
 use jni::JNIEnv;
 use lazy_static::lazy_static;
 use mut_static::MutStatic;
 use prost::Message;
+use std::convert::TryFrom;
 use std::ffi::CStr;
 use std::os::raw::c_char;
 
@@ -23,25 +26,136 @@ pub unsafe extern "C" fn bridge_init(raw_data: *const u8, len: usize) {
         .unwrap();
 }
 
-unsafe fn bridge_search_internal(query: &str) -> Vec<u8> {
-    let res = ENGINE.read().unwrap().search(query);
+fn encode_response(res: &ServiceResponse) -> Vec<u8> {
     let mut buf = Vec::new();
     res.encode(&mut buf).unwrap();
 
     buf
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn bridge_search(bytes: *const c_char) -> FfiBuffer {
+unsafe fn bridge_search_internal(query: &str, options: &SearchOptions) -> Vec<u8> {
+    let res = ENGINE.read().unwrap().search_with_options(query, options);
+    encode_response(&res)
+}
+
+fn bridge_get_verse_internal(book: i32, chapter: u32, verse: u32) -> Vec<u8> {
+    let key = VerseKey {
+        book,
+        chapter,
+        verse,
+    };
+    let results: Vec<_> = ENGINE.read().unwrap().get_verse(&key).into_iter().collect();
+    encode_response(&ServiceResponse::from(results))
+}
+
+fn bridge_get_chapter_internal(book: i32, chapter: u32) -> Vec<u8> {
+    let results = Book::from_i32(book)
+        .map(|book| ENGINE.read().unwrap().get_chapter(book, chapter))
+        .unwrap_or_default();
+    encode_response(&ServiceResponse::from(results))
+}
+
+fn bridge_get_range_internal(start: VerseKey, end: VerseKey) -> Vec<u8> {
+    let results = ENGINE.read().unwrap().get_range(&start, &end);
+    encode_response(&ServiceResponse::from(results))
+}
+
+fn bridge_complete_internal(text: &str, n: usize) -> Vec<u8> {
+    let completions = Completions::from(ENGINE.read().unwrap().complete(text, n));
+    let mut buf = Vec::new();
+    completions.encode(&mut buf).unwrap();
+    buf
+}
+
+/// Converts a Java int to a chapter or verse number, negative numbers are never
+/// valid so they become a number the engine rejects rather than wrapping around
+fn jint_to_u32(n: jint) -> u32 {
+    u32::try_from(n).unwrap_or(u32::MAX)
+}
+
+unsafe fn query_from_c_str<'a>(bytes: *const c_char) -> &'a str {
     let c_str = CStr::from_ptr(bytes);
-    let query = match c_str.to_str() {
+    match c_str.to_str() {
         Err(_) => "",
         Ok(string) => string,
-    };
-    let buf = bridge_search_internal(query);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bridge_search(bytes: *const c_char) -> FfiBuffer {
+    let query = query_from_c_str(bytes);
+    let buf = bridge_search_internal(query, &SearchOptions::default());
     FfiBuffer::from_vec(buf)
 }
 
+/// Searches with options given as an encoded SearchOptions protobuf, options
+/// which cannot be decoded are ignored
+///
+/// # Safety
+///
+/// `bytes` must point to a null-terminated string and `raw_options` must point
+/// to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn bridge_search_with_options(
+    bytes: *const c_char,
+    raw_options: *const u8,
+    len: usize,
+) -> FfiBuffer {
+    let query = query_from_c_str(bytes);
+    let options =
+        decode_search_options(std::slice::from_raw_parts(raw_options, len)).unwrap_or_default();
+    let buf = bridge_search_internal(query, &options);
+    FfiBuffer::from_vec(buf)
+}
+
+/// Gets a single verse in every translation, results are empty if the verse
+/// does not exist
+#[no_mangle]
+pub extern "C" fn bridge_get_verse(book: i32, chapter: u32, verse: u32) -> FfiBuffer {
+    FfiBuffer::from_vec(bridge_get_verse_internal(book, chapter, verse))
+}
+
+/// Gets every verse of a chapter in every translation
+#[no_mangle]
+pub extern "C" fn bridge_get_chapter(book: i32, chapter: u32) -> FfiBuffer {
+    FfiBuffer::from_vec(bridge_get_chapter_internal(book, chapter))
+}
+
+/// Gets every verse in an inclusive range in every translation
+#[no_mangle]
+pub extern "C" fn bridge_get_range(
+    start_book: i32,
+    start_chapter: u32,
+    start_verse: u32,
+    end_book: i32,
+    end_chapter: u32,
+    end_verse: u32,
+) -> FfiBuffer {
+    let start = VerseKey {
+        book: start_book,
+        chapter: start_chapter,
+        verse: start_verse,
+    };
+    let end = VerseKey {
+        book: end_book,
+        chapter: end_chapter,
+        verse: end_verse,
+    };
+    FfiBuffer::from_vec(bridge_get_range_internal(start, end))
+}
+
+/// Completes the last word of the text with up to `n` of the most common words
+/// starting with it, returned as an encoded Completions protobuf
+///
+/// # Safety
+///
+/// `bytes` must point to a null-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn bridge_complete(bytes: *const c_char, n: u32) -> FfiBuffer {
+    let text = query_from_c_str(bytes);
+    FfiBuffer::from_vec(bridge_complete_internal(text, n as usize))
+}
+
 #[no_mangle]
 pub extern "C" fn bridge_search_free(buf: FfiBuffer) {
     std::mem::forget(buf);
@@ -66,6 +180,92 @@ pub unsafe extern "system" fn Java_bible_instant_ui_main_MainViewModel_bridgeSea
     q: JString,
 ) -> jbyteArray {
     let q_string: String = env.get_string(q).expect("Couldn't get Java string!").into();
-    let buf = bridge_search_internal(&q_string);
+    let buf = bridge_search_internal(&q_string, &SearchOptions::default());
+    env.byte_array_from_slice(&buf).unwrap()
+}
+
+/// Searches with options given as an encoded SearchOptions protobuf, options
+/// which cannot be decoded are ignored
+///
+/// # Safety
+///
+/// Must only be called by the JVM with valid `env`, `q` and `options` references
+#[no_mangle]
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn Java_bible_instant_ui_main_MainViewModel_bridgeSearchWithOptions(
+    env: JNIEnv,
+    _class: JObject,
+    q: JString,
+    options: jbyteArray,
+) -> jbyteArray {
+    let q_string: String = env.get_string(q).expect("Couldn't get Java string!").into();
+    let options_bytes = env.convert_byte_array(options).unwrap();
+    let options = decode_search_options(&options_bytes).unwrap_or_default();
+    let buf = bridge_search_internal(&q_string, &options);
+    env.byte_array_from_slice(&buf).unwrap()
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn Java_bible_instant_ui_main_MainViewModel_bridgeGetVerse(
+    env: JNIEnv,
+    _class: JObject,
+    book: jint,
+    chapter: jint,
+    verse: jint,
+) -> jbyteArray {
+    let buf = bridge_get_verse_internal(book, jint_to_u32(chapter), jint_to_u32(verse));
+    env.byte_array_from_slice(&buf).unwrap()
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn Java_bible_instant_ui_main_MainViewModel_bridgeGetChapter(
+    env: JNIEnv,
+    _class: JObject,
+    book: jint,
+    chapter: jint,
+) -> jbyteArray {
+    let buf = bridge_get_chapter_internal(book, jint_to_u32(chapter));
+    env.byte_array_from_slice(&buf).unwrap()
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+#[allow(clippy::too_many_arguments)]
+pub extern "system" fn Java_bible_instant_ui_main_MainViewModel_bridgeGetRange(
+    env: JNIEnv,
+    _class: JObject,
+    start_book: jint,
+    start_chapter: jint,
+    start_verse: jint,
+    end_book: jint,
+    end_chapter: jint,
+    end_verse: jint,
+) -> jbyteArray {
+    let start = VerseKey {
+        book: start_book,
+        chapter: jint_to_u32(start_chapter),
+        verse: jint_to_u32(start_verse),
+    };
+    let end = VerseKey {
+        book: end_book,
+        chapter: jint_to_u32(end_chapter),
+        verse: jint_to_u32(end_verse),
+    };
+    let buf = bridge_get_range_internal(start, end);
+    env.byte_array_from_slice(&buf).unwrap()
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "system" fn Java_bible_instant_ui_main_MainViewModel_bridgeComplete(
+    env: JNIEnv,
+    _class: JObject,
+    q: JString,
+    n: jint,
+) -> jbyteArray {
+    let q_string: String = env.get_string(q).expect("Couldn't get Java string!").into();
+    let buf = bridge_complete_internal(&q_string, n as usize);
     env.byte_array_from_slice(&buf).unwrap()
 }
@@ -1,4 +1,6 @@
+use engine::proto::data::{Book, VerseKey};
 use engine::proto::engine::decode_index_data;
+use engine::proto::service::{decode_search_options, Completions, Response as ServiceResponse};
 use engine::VersearchIndex;
 use lazy_static::lazy_static;
 use mut_static::MutStatic;
@@ -12,12 +14,19 @@ lazy_static! {
     static ref ENGINE: MutStatic<VersearchIndex> = MutStatic::new();
 }
 
+#[wasm_bindgen]
+extern "C" {
+    // Available on both windows and web workers
+    #[wasm_bindgen(js_namespace = performance)]
+    fn now() -> f64;
+}
+
 #[wasm_bindgen]
 pub extern "C" fn init(data: &[u8]) {
     let proto = decode_index_data(data).unwrap();
-    ENGINE
-        .set(VersearchIndex::from_index_data_proto_struct(proto))
-        .unwrap();
+    let mut index = VersearchIndex::from_index_data_proto_struct(proto);
+    index.set_clock(now);
+    ENGINE.set(index).unwrap();
 }
 
 #[wasm_bindgen]
@@ -27,3 +36,76 @@ pub extern "C" fn search(query: &str) -> Vec<u8> {
     res.encode(&mut buf).unwrap();
     buf
 }
+
+/// Searches with options given as an encoded SearchOptions protobuf, options
+/// which cannot be decoded are ignored
+#[wasm_bindgen]
+pub extern "C" fn search_with_options(query: &str, options: &[u8]) -> Vec<u8> {
+    let options = decode_search_options(options).unwrap_or_default();
+    let res = ENGINE.read().unwrap().search_with_options(query, &options);
+    let mut buf = Vec::new();
+    res.encode(&mut buf).unwrap();
+    buf
+}
+
+/// Gets a single verse in every translation, results are empty if the verse
+/// does not exist
+#[wasm_bindgen]
+pub extern "C" fn get_verse(book: i32, chapter: u32, verse: u32) -> Vec<u8> {
+    let key = VerseKey {
+        book,
+        chapter,
+        verse,
+    };
+    let results: Vec<_> = ENGINE.read().unwrap().get_verse(&key).into_iter().collect();
+    let mut buf = Vec::new();
+    ServiceResponse::from(results).encode(&mut buf).unwrap();
+    buf
+}
+
+/// Gets every verse of a chapter in every translation
+#[wasm_bindgen]
+pub extern "C" fn get_chapter(book: i32, chapter: u32) -> Vec<u8> {
+    let results = Book::from_i32(book)
+        .map(|book| ENGINE.read().unwrap().get_chapter(book, chapter))
+        .unwrap_or_default();
+    let mut buf = Vec::new();
+    ServiceResponse::from(results).encode(&mut buf).unwrap();
+    buf
+}
+
+/// Gets every verse in an inclusive range in every translation
+#[wasm_bindgen]
+pub extern "C" fn get_range(
+    start_book: i32,
+    start_chapter: u32,
+    start_verse: u32,
+    end_book: i32,
+    end_chapter: u32,
+    end_verse: u32,
+) -> Vec<u8> {
+    let start = VerseKey {
+        book: start_book,
+        chapter: start_chapter,
+        verse: start_verse,
+    };
+    let end = VerseKey {
+        book: end_book,
+        chapter: end_chapter,
+        verse: end_verse,
+    };
+    let res = ServiceResponse::from(ENGINE.read().unwrap().get_range(&start, &end));
+    let mut buf = Vec::new();
+    res.encode(&mut buf).unwrap();
+    buf
+}
+
+/// Completes the last word of the text with up to `n` of the most common words
+/// starting with it, returned as an encoded Completions protobuf
+#[wasm_bindgen]
+pub extern "C" fn complete(text: &str, n: usize) -> Vec<u8> {
+    let completions = Completions::from(ENGINE.read().unwrap().complete(text, n));
+    let mut buf = Vec::new();
+    completions.encode(&mut buf).unwrap();
+    buf
+}
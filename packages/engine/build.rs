@@ -13,6 +13,10 @@ fn main() {
         ".instantbible.service.Response",
         "#[derive(serde::Serialize)]",
     );
+    prost.type_attribute(
+        ".instantbible.service.Completions",
+        "#[derive(serde::Serialize)]",
+    );
     prost.type_attribute(
         ".instantbible.service.Response.VerseResult.Ranking",
         "#[derive(Eq)]",
@@ -0,0 +1,78 @@
+use crate::ranking::RankingRules;
+
+/// Tunes how query tokens are expanded against the index
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    /// Tokens at least this long may match with one typo
+    pub typo_1_len: usize,
+    /// Tokens at least this long may match with two typos
+    pub typo_2_len: usize,
+    /// Look for typos even when the prefix search found matches, otherwise typos
+    /// are only considered for tokens which match nothing
+    pub typos_with_prefix: bool,
+    /// Only treat the last token as a prefix (search-as-you-type), earlier tokens
+    /// must match exactly or with typos
+    pub prefix_last_token_only: bool,
+    /// The maximum number of index tokens a single query token may expand to,
+    /// shortest tokens first (unlimited when `None`)
+    pub max_expansions: Option<usize>,
+    /// The maximum number of index tokens a regex token may match, in
+    /// lexicographical order
+    pub max_regex_expansions: usize,
+    /// Match words sharing a stem with a query token (e.g., "loveth" for "loved")
+    /// if the index was built with stems
+    pub stemming: bool,
+    /// Match synonyms of query tokens (e.g., "thee" for "you") from the index's
+    /// synonym list
+    pub synonyms: bool,
+    /// Orders results, search options may name other rules for a single search
+    pub ranking_rules: RankingRules,
+}
+
+impl SearchConfig {
+    /// Returns the number of typos allowed for a token, depending on its length in
+    /// characters
+    pub fn typo_distance(&self, token: &str) -> u32 {
+        let len = token.chars().count();
+        if len >= self.typo_2_len {
+            2
+        } else if len >= self.typo_1_len {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            typo_1_len: 4,
+            typo_2_len: 8,
+            typos_with_prefix: false,
+            prefix_last_token_only: false,
+            max_expansions: None,
+            max_regex_expansions: 200,
+            stemming: true,
+            synonyms: true,
+            ranking_rules: RankingRules::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typo_distance() {
+        let config = SearchConfig::default();
+        assert_eq!(config.typo_distance("GOD"), 0);
+        assert_eq!(config.typo_distance("LOVE"), 1);
+        assert_eq!(config.typo_distance("BEGOTTE"), 1);
+        assert_eq!(config.typo_distance("BEGOTTEN"), 2);
+        // Lengths are counted in characters, not bytes
+        assert_eq!(config.typo_distance("ΦΩΣ"), 0);
+        assert_eq!(config.typo_distance("ΛΟΓΟΣ"), 1);
+    }
+}
@@ -1,12 +1,18 @@
 use crate::proto::service::response::verse_result::Ranking as ServiceRanking;
-use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The maximum distance counted between two query words
+static MAX_PROXIMITY: usize = 8;
 
 #[derive(Eq)]
 pub struct InternalServiceRanking {
     pub ranking: ServiceRanking,
     idx: usize,
+    /// Disabled translations were not requested and always rank last
+    enabled: bool,
     query_word_matches: BTreeSet<usize>,
+    /// Query word => Positions of matching tokens in the verse
+    query_word_positions: BTreeMap<usize, Vec<u16>>,
 }
 
 impl InternalServiceRanking {
@@ -16,12 +22,29 @@ impl InternalServiceRanking {
                 typos: 0,
                 query_words: 0,
                 exact: 0,
+                proximity: 0,
+                stemmed: 0,
+                synonyms: 0,
             },
             idx,
+            enabled: true,
             query_word_matches: BTreeSet::new(),
+            query_word_positions: BTreeMap::new(),
         }
     }
 
+    pub fn disable(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
     pub fn inc_typos(&mut self) {
         self.ranking.typos += 1;
     }
@@ -35,6 +58,50 @@ impl InternalServiceRanking {
         self.ranking.exact += 1;
     }
 
+    pub fn inc_stemmed(&mut self) {
+        self.ranking.stemmed += 1;
+    }
+
+    pub fn inc_synonyms(&mut self) {
+        self.ranking.synonyms += 1;
+    }
+
+    pub fn add_positions(&mut self, query_word: usize, positions: &[u16]) {
+        self.query_word_positions
+            .entry(query_word)
+            .or_default()
+            .extend(positions);
+    }
+
+    /// Sums the distances between each pair of consecutive matched query words,
+    /// should be called once all positions have been added
+    // `unsigned_abs` is newer than the toolchain the Docker image builds with
+    #[allow(clippy::cast_abs_to_unsigned)]
+    pub fn compute_proximity(&mut self) {
+        self.ranking.proximity = self
+            .query_word_positions
+            .iter()
+            .zip(self.query_word_positions.iter().skip(1))
+            .map(|((q1, p1), (q2, p2))| {
+                // Words missing from the query (e.g., stop words) are expected gaps
+                let expected = (q2 - q1) as isize;
+                p1.iter()
+                    .flat_map(|a| p2.iter().map(move |b| (*a as isize, *b as isize)))
+                    .map(|(a, b)| {
+                        let gap = b - a;
+                        if gap > 0 {
+                            (gap - expected).abs() as usize
+                        } else {
+                            // Out of order words are never better than in order words
+                            gap.abs() as usize + 1
+                        }
+                    })
+                    .min()
+                    .map_or(MAX_PROXIMITY, |d| d.min(MAX_PROXIMITY))
+            })
+            .sum::<usize>() as i32;
+    }
+
     pub fn to_service_ranking(&self) -> ServiceRanking {
         self.ranking.clone()
     }
@@ -47,27 +114,39 @@ impl PartialEq for InternalServiceRanking {
     }
 }
 
-impl Ord for InternalServiceRanking {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Sort by matched query words descending (more words matched == higher rank)
-        if self.ranking.query_words != other.ranking.query_words {
-            return other.ranking.query_words.cmp(&self.ranking.query_words);
-        }
-        // Sort by number of exactly matching query words descending (more exact matches == higher rank)
-        if self.ranking.exact != other.ranking.exact {
-            return other.ranking.exact.cmp(&self.ranking.exact);
-        }
-        // Sort by typos ascending (fewer typos == higher rank)
-        if self.ranking.typos != other.ranking.typos {
-            return self.ranking.typos.cmp(&other.ranking.typos);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proximity(positions: &[(usize, &[u16])]) -> i32 {
+        let mut ranking = InternalServiceRanking::new(0);
+        for (query_word, positions) in positions {
+            ranking.add_positions(*query_word, positions);
         }
-        // Fall back to translation index
-        self.idx.cmp(&other.idx)
+        ranking.compute_proximity();
+        ranking.ranking.proximity
     }
-}
 
-impl PartialOrd for InternalServiceRanking {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+    #[test]
+    fn test_compute_proximity() {
+        // Adjacent words in query order
+        assert_eq!(proximity(&[(0, &[3]), (1, &[4]), (2, &[5])]), 0);
+        // The closest pair of positions counts
+        assert_eq!(proximity(&[(0, &[1, 10]), (1, &[11])]), 0);
+        // Words separated by a gap
+        assert_eq!(proximity(&[(0, &[3]), (1, &[6])]), 2);
+        // A query word missing between matched words (e.g., a stop word) is expected
+        assert_eq!(proximity(&[(0, &[3]), (2, &[5])]), 0);
+        // Out of order words rank below in order words at the same distance
+        assert_eq!(proximity(&[(0, &[4]), (1, &[3])]), 2);
+        assert_eq!(proximity(&[(0, &[4]), (1, &[4])]), 1);
+        // Distances are capped
+        assert_eq!(proximity(&[(0, &[0]), (1, &[100])]), MAX_PROXIMITY as i32);
+        assert_eq!(
+            proximity(&[(0, &[0]), (1, &[100]), (2, &[200])]),
+            2 * MAX_PROXIMITY as i32
+        );
+        // A single word has nothing to be near
+        assert_eq!(proximity(&[(0, &[7])]), 0);
     }
 }
@@ -1,5 +1,6 @@
 use crate::proto::engine::ReverseIndexEntry as ReverseIndexEntryBytes;
-use fst::{raw, Map as FstMap};
+use crate::proto::service::KeyRange;
+use fst::{raw, IntoStreamer, Map as FstMap, Streamer};
 
 /// Different strings can end up creating the same token (e.g., it's and its both
 /// produce ITS); therefore, it is important to account for this in the index
@@ -11,6 +12,8 @@ pub struct ReverseIndexEntry {
     counts: Vec<Vec<u64>>, // TODO: does this need to be u64? Refactoring would just mean changing the from_bytes stuff
     /// VerseKey => Vec<Highlight Word Ids>
     highlights: Vec<Vec<u64>>,
+    /// VerseKey => Token positions grouped by translation (decoded on demand)
+    positions: Vec<Vec<u8>>,
 }
 
 impl ReverseIndexEntry {
@@ -45,6 +48,7 @@ impl ReverseIndexEntry {
                     v
                 })
                 .collect(),
+            positions: input.positions_map_data.clone(),
         }
     }
 
@@ -61,6 +65,62 @@ impl ReverseIndexEntry {
         self.map.stream().into_byte_keys()
     }
 
+    /// Returns the verse keys which fall within the given ranges (all keys if `None`)
+    pub fn get_verse_keys_in(&self, ranges: Option<&[KeyRange]>) -> Vec<Vec<u8>> {
+        match ranges {
+            None => self.get_verse_keys(),
+            Some(ranges) => ranges
+                .iter()
+                .flat_map(|(start, end)| {
+                    self.map
+                        .range()
+                        .ge(start.to_be_bytes())
+                        .le(end.to_be_bytes())
+                        .into_stream()
+                        .into_byte_keys()
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the number of verses which fall within the given ranges (all verses if `None`)
+    pub fn len_in(&self, ranges: Option<&[KeyRange]>) -> usize {
+        match ranges {
+            None => self.len(),
+            Some(ranges) => ranges
+                .iter()
+                .map(|(start, end)| {
+                    let mut stream = self
+                        .map
+                        .range()
+                        .ge(start.to_be_bytes())
+                        .le(end.to_be_bytes())
+                        .into_stream();
+                    let mut count = 0;
+                    while stream.next().is_some() {
+                        count += 1;
+                    }
+                    count
+                })
+                .sum(),
+        }
+    }
+
+    /// Returns the positions of this token within the given verse and translation
+    pub fn get_positions(&self, verse_key: &[u8], translation: usize) -> Option<Vec<u16>> {
+        let idx = self.map.get(verse_key)? as usize;
+        let counts = &self.counts[idx];
+        let start = counts[..translation].iter().sum::<u64>() as usize;
+        let end = start + counts[translation] as usize;
+        let bytes = self.positions.get(idx)?.get((start * 2)..(end * 2))?;
+        Some(
+            bytes
+                .chunks(2)
+                .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+                .collect(),
+        )
+    }
+
     pub fn get_highlights(&self, verse_key: &[u8]) -> Option<&Vec<u64>> {
         let idx = self.map.get(verse_key);
         idx.map(|idx| &self.highlights[idx as usize])
@@ -1,33 +1,46 @@
 use super::InternalServiceRanking;
 use crate::proto::data::VerseKey;
 use crate::proto::service::response::verse_result::Ranking as ServiceRanking;
+use crate::ranking::RankingRules;
 use crate::TRANSLATION_COUNT;
-use std::cmp::Ordering;
 
 #[derive(PartialEq, Eq)]
 pub struct VerseMatch {
     pub key: VerseKey,
     pub highlights: Vec<u64>,
     rankings: Vec<InternalServiceRanking>,
+    /// BM25 relevance of the most relevant translation, in thousandths
+    relevance: u64,
     pub popularity: u64,
 }
 
 impl VerseMatch {
-    pub fn new(key: VerseKey, popularity: u64) -> Self {
-        let mut rankings = Vec::with_capacity(TRANSLATION_COUNT);
-
-        for i in 0..TRANSLATION_COUNT {
-            rankings.push(InternalServiceRanking::new(i));
-        }
+    /// Creates a new match, `translations` flags which translations were requested
+    pub fn new(key: VerseKey, popularity: u64, translations: &[bool]) -> Self {
+        let rankings = (0..TRANSLATION_COUNT)
+            .zip(translations)
+            .map(|(i, enabled)| {
+                let mut ranking = InternalServiceRanking::new(i);
+                if !enabled {
+                    ranking.disable();
+                }
+                ranking
+            })
+            .collect();
 
         Self {
             key,
             rankings,
             highlights: Vec::new(),
+            relevance: 0,
             popularity,
         }
     }
 
+    pub fn includes_translation(&self, idx: usize) -> bool {
+        self.rankings[idx].is_enabled()
+    }
+
     pub fn inc_typos(&mut self, idx: usize) {
         self.rankings[idx].inc_typos();
     }
@@ -40,6 +53,37 @@ impl VerseMatch {
         self.rankings[idx].inc_exact();
     }
 
+    pub fn inc_stemmed(&mut self, idx: usize) {
+        self.rankings[idx].inc_stemmed();
+    }
+
+    pub fn inc_synonyms(&mut self, idx: usize) {
+        self.rankings[idx].inc_synonyms();
+    }
+
+    pub fn add_positions(&mut self, idx: usize, query_word: usize, positions: &[u16]) {
+        self.rankings[idx].add_positions(query_word, positions);
+    }
+
+    pub fn compute_proximity(&mut self) {
+        for ranking in self.rankings.iter_mut() {
+            ranking.compute_proximity();
+        }
+    }
+
+    pub fn set_relevance(&mut self, relevance: f64) {
+        self.relevance = (relevance * 1000.0).round() as u64;
+    }
+
+    pub fn relevance(&self) -> u64 {
+        self.relevance
+    }
+
+    /// Returns true if any translation matched at least one query word
+    pub fn has_query_words(&self) -> bool {
+        self.rankings.iter().any(|r| r.ranking.query_words > 0)
+    }
+
     pub fn extend_highlights(&mut self, hi: &[u64]) {
         self.highlights.extend(hi)
     }
@@ -51,34 +95,49 @@ impl VerseMatch {
             .collect()
     }
 
-    pub fn top_translation(&self) -> i32 {
+    /// Returns the ranking of the translation which best matched the query
+    /// according to the given rules
+    pub fn top_ranking(&self, rules: &RankingRules) -> &InternalServiceRanking {
         self.rankings
             .iter()
-            .enumerate()
-            .min_by(|(_, m1), (_, m2)| m1.cmp(m2))
+            .min_by(|r1, r2| rules.cmp_translations(r1, r2))
             .unwrap()
-            .0 as i32
     }
-}
 
-impl PartialOrd for VerseMatch {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let self_min = self.rankings.iter().min()?;
-        let other_min = other.rankings.iter().min()?;
-
-        if self_min.ranking.query_words != other_min.ranking.query_words {
-            other_min
-                .ranking
-                .query_words
-                .partial_cmp(&self_min.ranking.query_words)
-        } else {
-            other.popularity.partial_cmp(&self.popularity)
-        }
+    pub fn top_translation(&self, rules: &RankingRules) -> i32 {
+        self.top_ranking(rules).idx() as i32
     }
 }
 
-impl Ord for VerseMatch {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translation_flags() {
+        let key = VerseKey {
+            book: 42,
+            chapter: 3,
+            verse: 16,
+        };
+        let mut verse_match = VerseMatch::new(key, 0, &[false, true, true]);
+        assert!(!verse_match.includes_translation(0));
+        assert!(verse_match.includes_translation(1));
+        assert!(verse_match.includes_translation(2));
+
+        // An unrequested translation never ranks first, however well it matched
+        verse_match.inc_query_words(0, 0);
+        verse_match.inc_query_words(0, 1);
+        verse_match.inc_exact(0);
+        verse_match.inc_query_words(2, 0);
+        verse_match.inc_typos(2);
+        let rules = RankingRules::default();
+        assert_eq!(verse_match.top_translation(&rules), 2);
+        // Requested translations which matched rank before those which did not
+        verse_match.inc_query_words(1, 0);
+        assert_eq!(verse_match.top_translation(&rules), 1);
+        // With nothing matched, the first requested translation is the top
+        let verse_match = VerseMatch::new(key, 0, &[false, false, true]);
+        assert_eq!(verse_match.top_translation(&rules), 2);
     }
 }
@@ -1,32 +1,63 @@
+mod config;
 mod data;
 pub mod proto;
+pub mod query;
+pub mod ranking;
+pub mod reference;
+mod regex_token;
+mod relevance;
+mod snippet;
+mod stemmer;
+mod stop_words;
+mod synonyms;
+mod timer;
+pub mod tokenizer;
 pub mod util;
+mod wildcard;
 
-use crate::proto::engine::IndexData;
+use crate::proto::engine::{IndexData, StopWordMode};
 use data::{ReverseIndex, ReverseIndexEntry, VerseMatch};
-use fst::{automaton, raw, Automaton, IntoStreamer, Map as FstMap};
+use fst::{automaton, raw, Automaton, IntoStreamer, Map as FstMap, Streamer};
 use itertools::Itertools;
-use proto::data::{Translation, VerseKey};
-use proto::service::{response::VerseResult, Response as ServiceResponse};
-use std::collections::HashMap;
-use util::{tokenize, translation_verses_bytes_key, Tokenized};
-// Previously this module was using wasm-timer, however, it turns out wasm-timer's Instant::now()
-// doesn't work in web workers https://github.com/tomaka/wasm-timer/issues/12
-// use wasm_timer::Instant;
+use proto::data::{Book, Translation, VerseKey};
+use proto::service::response::verse_result::{Context, Snippet, Span, Spans};
+use proto::service::response::VerseResult;
+use proto::service::{KeyRange, Response as ServiceResponse};
+use query::{parse_query, Occur, Query};
+use ranking::RankingRules;
+use reference::{parse_reference, Reference};
+use regex_token::{build_regex, regex_pattern};
+use relevance::{bm25, idf};
+use snippet::snippet;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use stemmer::stem_prefix;
+use stop_words::StopWords;
+use synonyms::Synonyms;
+use timer::{default_clock, PhaseTimer};
+use tokenizer::{tokenizer_by_name, Tokenizer, ENGLISH};
+use util::{translation_verses_bytes_key, Tokenized};
+use wildcard::{is_wildcard, suffix_literal, Wildcard};
 
+pub use config::SearchConfig;
+pub use proto::service::SearchOptions;
+pub use timer::Clock;
 pub use util::Config;
 
-static MAX_RESULTS: usize = 20;
-static TYPO_1_LEN: usize = 4;
-static TYPO_2_LEN: usize = 8;
+static DEFAULT_LIMIT: usize = 20;
+static MAX_LIMIT: usize = 100;
+static MAX_CONTEXT: usize = 10;
+static DEFAULT_COMPLETIONS: usize = 10;
 pub static TRANSLATION_COUNT: usize = Translation::Total as usize;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
 enum MatchType {
     Exact = 0,
-    Prefix = 1,
-    Typo = 2,
+    Stemmed = 1,
+    Synonym = 2,
+    Prefix = 3,
+    Typo = 4,
 }
 
 struct ReverseIndexEntryWithMatch<'a> {
@@ -35,161 +66,550 @@ struct ReverseIndexEntryWithMatch<'a> {
     qidx: usize,
 }
 
+/// An indexed word of a quoted phrase and its offset from the start of the phrase
+struct PhraseEntry<'a> {
+    entry: &'a ReverseIndexEntry,
+    offset: usize,
+}
+
+/// Conditions every result must meet beyond matching a query word
+struct Constraints<'a> {
+    /// Quoted phrases which must appear in full
+    phrases: Vec<Vec<PhraseEntry<'a>>>,
+    /// Required clauses, each one of the entries must appear
+    required: Vec<Vec<&'a ReverseIndexEntry>>,
+    /// Excluded words, none of the entries may appear
+    excluded: Vec<&'a ReverseIndexEntry>,
+}
+
+impl<'a> Constraints<'a> {
+    fn is_empty(&self) -> bool {
+        self.phrases.is_empty() && self.required.is_empty() && self.excluded.is_empty()
+    }
+
+    /// Determines whether the given verse and translation meet every constraint
+    fn matches(&self, verse_key: &[u8], translation: usize) -> bool {
+        let contains = |e: &ReverseIndexEntry| matches!(e.get_counts(verse_key), Some(counts) if counts[translation] > 0);
+        self.phrases
+            .iter()
+            .all(|p| VersearchIndex::phrase_matches(p, verse_key, translation))
+            && self
+                .required
+                .iter()
+                .all(|entries| entries.iter().any(|e| contains(e)))
+            && !self.excluded.iter().any(|e| contains(e))
+    }
+}
+
 pub struct VersearchIndex {
     fst_map: FstMap<Vec<u8>>,
+    reversed_fst_map: FstMap<Vec<u8>>,
+    stem_fst_map: FstMap<Vec<u8>>,
     reverse_index: ReverseIndex,
     highlight_words: Vec<String>,
     translation_verses_map: FstMap<Vec<u8>>,
     translation_verses_strings: Vec<String>,
+    /// The number of tokens in each verse, parallel to `translation_verses_strings`
+    translation_verse_lengths: Vec<u32>,
+    /// The average number of tokens in a verse, by translation id
+    average_verse_lengths: Vec<f64>,
     verse_popularity: FstMap<Vec<u8>>,
+    /// The tokenizer each translation was indexed with, by translation id
+    tokenizers: Vec<&'static dyn Tokenizer>,
+    stop_words: StopWords,
+    synonyms: Synonyms,
+    clock: Option<Clock>,
+    config: SearchConfig,
 }
 
 impl VersearchIndex {
     #[allow(clippy::new_without_default)]
     pub fn from_index_data_proto_struct(index_data: IndexData) -> Self {
+        // Indexes built before tokenizers were recorded are English
+        let tokenizers = (0..TRANSLATION_COUNT)
+            .map(|i| {
+                index_data
+                    .tokenizers
+                    .get(i)
+                    .and_then(|name| tokenizer_by_name(name))
+                    .unwrap_or(&ENGLISH)
+            })
+            .collect();
+        let translation_verses_map = FstMap::from(
+            raw::Fst::new(index_data.translation_verses)
+                .expect("Could not load map from verses bytes"),
+        );
+        let translation_verse_lengths = index_data.translation_verse_lengths;
+        // Translation id => (Total tokens, Verses)
+        let mut totals = vec![(0, 0); TRANSLATION_COUNT];
+        let mut stream = translation_verses_map.stream();
+        while let Some((key, idx)) = stream.next() {
+            let length = translation_verse_lengths.get(idx as usize);
+            if let (Some(total), Some(length)) = (totals.get_mut(key[0] as usize), length) {
+                total.0 += *length as u64;
+                total.1 += 1;
+            }
+        }
+        let average_verse_lengths = totals
+            .into_iter()
+            .map(|(tokens, verses)| match verses {
+                0 => 0.0,
+                verses => tokens as f64 / verses as f64,
+            })
+            .collect();
         VersearchIndex {
             fst_map: FstMap::from(
                 raw::Fst::new(index_data.fst).expect("Could not load map from FST bytes"),
             ),
+            // Indexes built before suffix searches have no reversed FST, their suffix
+            // patterns are matched like any other wildcard
+            reversed_fst_map: raw::Fst::new(index_data.reversed_fst)
+                .map(FstMap::from)
+                .unwrap_or_else(|_| FstMap::default()),
+            // Indexes built without stems have no stem FST at all
+            stem_fst_map: raw::Fst::new(index_data.stem_fst)
+                .map(FstMap::from)
+                .unwrap_or_else(|_| FstMap::default()),
             reverse_index: index_data
                 .reverse_index_entries
                 .iter()
                 .map(|b| ReverseIndexEntry::from_bytes_struct(b))
                 .collect(),
             highlight_words: index_data.highlight_words,
-            translation_verses_map: FstMap::from(
-                raw::Fst::new(index_data.translation_verses)
-                    .expect("Could not load map from verses bytes"),
-            ),
+            translation_verses_map,
             translation_verses_strings: index_data.translation_verses_strings,
+            translation_verse_lengths,
+            average_verse_lengths,
             verse_popularity: FstMap::from(
                 raw::Fst::new(index_data.popularity)
                     .expect("Could not load map from popularity bytes"),
             ),
+            tokenizers,
+            stop_words: StopWords::new(
+                index_data.stop_words.into_iter().collect(),
+                StopWordMode::from_i32(index_data.stop_word_mode).unwrap_or(StopWordMode::Drop),
+            ),
+            synonyms: Synonyms::new(
+                &index_data
+                    .synonyms
+                    .into_iter()
+                    .map(|group| group.words)
+                    .collect::<Vec<_>>(),
+            ),
+            clock: default_clock(),
+            config: SearchConfig::default(),
         }
     }
 
+    /// Returns the configuration used to expand query tokens
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    /// Sets the configuration used to expand query tokens
+    pub fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    /// Sets the clock used to report timings with search responses
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+
     #[inline]
-    fn traverse_fst(&self, tokens: &[Tokenized]) -> HashMap<u64, ReverseIndexEntryWithMatch> {
+    fn traverse_fst(
+        &self,
+        query: &Query,
+        as_you_type: bool,
+    ) -> (
+        HashMap<u64, ReverseIndexEntryWithMatch<'_>>,
+        Vec<(usize, String)>,
+    ) {
         let mut found_indices: HashMap<u64, ReverseIndexEntryWithMatch> = HashMap::new();
+        // Query token index => The most common word similar to the misspelled token
+        let mut corrections = Vec::new();
 
-        for (qidx, Tokenized { token, .. }) in tokens.iter().enumerate() {
-            // Attempt a prefix search
-            let prefix_automaton = automaton::Str::new(&token).starts_with();
-            let mut results = self
-                .fst_map
-                .search(prefix_automaton)
-                .into_stream()
-                .into_str_vec()
-                .unwrap();
+        let config = &self.config;
+        let last_token_only = as_you_type || config.prefix_last_token_only;
+        for (tidx, Tokenized { token, .. }) in query.tokens.iter().enumerate() {
+            // Excluded words are not searched, alternatives of a clause count as the
+            // same query word
+            let qidx = query.clause_of(tidx);
+            let occur = query.clauses[qidx].occur;
+            let in_phrase = query.in_phrase(tidx);
+            if occur == Occur::MustNot
+                || (occur == Occur::Should && !in_phrase && self.stop_words.is_phrase_only(token))
+            {
+                continue;
+            }
 
-            // If nothing was found in the prefix search then this token was a typo
-            let is_typo = results.is_empty() && token.len() >= TYPO_1_LEN;
-            if is_typo {
-                let distance = if token.len() >= TYPO_2_LEN { 2 } else { 1 };
-                let lev_automaton = automaton::Levenshtein::new(&token, distance).unwrap();
-                results.extend(
-                    self.fst_map
-                        .search(&lev_automaton)
+            // Words in a phrase must match exactly as must complete words (all but the
+            // last) when searching as you type, regex and wildcard patterns match the
+            // words they describe, otherwise attempt a prefix search
+            let regex = regex_pattern(token);
+            let wildcard = regex.is_none() && is_wildcard(token);
+            let pattern = regex.is_some() || wildcard;
+            let complete =
+                in_phrase || pattern || (last_token_only && tidx < query.tokens.len() - 1);
+            // Expansions (e.g., "AND" for "AN") are held to the same rule as query
+            // tokens, stop words only match in phrases
+            let phrase_only = |word: &str| !in_phrase && self.stop_words.is_phrase_only(word);
+            let mut results: Vec<_> = if let Some(regex) = regex {
+                // Invalid patterns match nothing, others are capped as they may match
+                // huge numbers of words
+                match build_regex(regex) {
+                    Some(dfa) => {
+                        let mut stream = self.fst_map.search(dfa).into_stream();
+                        let mut results = Vec::new();
+                        while let Some((result, rid)) = stream.next() {
+                            if results.len() >= config.max_regex_expansions {
+                                break;
+                            }
+                            results.push((String::from_utf8_lossy(result).into_owned(), rid));
+                        }
+                        results
+                    }
+                    None => Vec::new(),
+                }
+            } else if let Some(suffix) =
+                suffix_literal(token).filter(|_| !self.reversed_fst_map.is_empty())
+            {
+                // Suffix searches are prefix searches over reversed tokens
+                let reversed: String = suffix.chars().rev().collect();
+                self.reversed_fst_map
+                    .search(automaton::Str::new(&reversed).starts_with())
+                    .into_stream()
+                    .into_str_vec()
+                    .unwrap()
+                    .into_iter()
+                    .map(|(result, rid)| (result.chars().rev().collect(), rid))
+                    .collect::<Vec<_>>()
+            } else if wildcard {
+                // Patterns which are too long match nothing
+                match Wildcard::new(token) {
+                    Some(wildcard) => self
+                        .fst_map
+                        .search(wildcard)
                         .into_stream()
                         .into_str_vec()
                         .unwrap(),
-                );
+                    None => Vec::new(),
+                }
+            } else if complete {
+                self.fst_map
+                    .search(automaton::Str::new(token))
+                    .into_stream()
+                    .into_str_vec()
+                    .unwrap()
+            } else {
+                self.fst_map
+                    .search(automaton::Str::new(token).starts_with())
+                    .into_stream()
+                    .into_str_vec()
+                    .unwrap()
+            }
+            .into_iter()
+            .filter(|(result, _)| !phrase_only(result))
+            .map(|(result, rid)| (result, rid, MatchType::Prefix))
+            .collect();
+
+            // If nothing was found in the prefix search then this token was a typo
+            let mut correction = None;
+            let distance = config.typo_distance(token);
+            let typos_allowed = !in_phrase && !pattern && distance > 0;
+            let is_typo = results.is_empty() && typos_allowed;
+            // Long tokens exceed the automaton's size limit and get no typos
+            let lev_automaton = Some(token)
+                .filter(|_| is_typo || (config.typos_with_prefix && typos_allowed))
+                .and_then(|token| automaton::Levenshtein::new(token, distance).ok());
+            if let Some(lev_automaton) = lev_automaton {
+                let typos: Vec<_> = self
+                    .fst_map
+                    .search(&lev_automaton)
+                    .into_stream()
+                    .into_str_vec()
+                    .unwrap()
+                    .into_iter()
+                    .filter(|(t, _)| !results.iter().any(|(r, _, _)| r == t) && !phrase_only(t))
+                    .map(|(result, rid)| (result, rid, MatchType::Typo))
+                    .collect();
+                if is_typo {
+                    correction = typos
+                        .iter()
+                        .max_by_key(|(_, rid, _)| self.reverse_index[*rid as usize].len())
+                        .map(|(word, _, _)| word.clone());
+                }
+                results.extend(typos);
             }
 
             // Sort results by token length (undo lexicographical iteration)
-            results.sort_by_key(|(t, _)| t.len());
+            results.sort_by_key(|(t, _, _)| t.chars().count());
+            if let Some(max) = config.max_expansions {
+                results.truncate(max);
+            }
+
+            // Words sharing a stem with the token (e.g., "LOVETH" for "LOVED") match
+            // too, except in phrases where words must match as they are
+            if let Some(stem) = query.stems[tidx]
+                .as_ref()
+                .filter(|_| config.stemming && !in_phrase)
+            {
+                let prefix = stem_prefix(stem);
+                let stemmed: Vec<_> = self
+                    .stem_fst_map
+                    .search(automaton::Str::new(&prefix).starts_with())
+                    .into_stream()
+                    .into_str_vec()
+                    .unwrap()
+                    .into_iter()
+                    .map(|(key, rid)| (key[prefix.len()..].to_string(), rid))
+                    .filter(|(key, rid)| {
+                        !results.iter().any(|(_, r, _)| r == rid) && !phrase_only(key)
+                    })
+                    .map(|(result, rid)| (result, rid, MatchType::Stemmed))
+                    .collect();
+                results.extend(stemmed);
+            }
+
+            // Synonyms of the token (e.g., "THEE" for "YOU") match too, except in phrases
+            if config.synonyms && !in_phrase && !pattern {
+                let synonyms: Vec<_> = self
+                    .synonyms
+                    .get(token)
+                    .filter_map(|synonym| {
+                        self.fst_map
+                            .get(synonym)
+                            .map(|rid| (synonym.to_string(), rid))
+                    })
+                    .filter(|(synonym, rid)| {
+                        !results.iter().any(|(_, r, _)| r == rid) && !phrase_only(synonym)
+                    })
+                    .map(|(result, rid)| (result, rid, MatchType::Synonym))
+                    .collect();
+                results.extend(synonyms);
+            }
+
+            // Only words which matched nothing but typos are corrected, a word which
+            // matched a stem or synonym (e.g., "THEE") is not misspelled
+            if let Some(word) = correction {
+                if results.iter().all(|(_, _, kind)| *kind == MatchType::Typo) {
+                    corrections.push((tidx, word));
+                }
+            }
 
             // Process found tokens
-            for (mid, (result, rid)) in results.iter().enumerate() {
+            for (mid, (result, rid, kind)) in results.iter().enumerate() {
                 let mut container =
                     found_indices
                         .entry(*rid)
                         .or_insert_with(|| ReverseIndexEntryWithMatch {
                             entry: &self.reverse_index[*rid as usize],
-                            match_type: if is_typo {
-                                MatchType::Typo
-                            } else {
-                                MatchType::Prefix
-                            },
+                            match_type: *kind,
                             qidx,
                         });
+                // A word matching the stem or a synonym of any token is at least a
+                // stemmed or synonym match
+                let expanded = *kind == MatchType::Stemmed || *kind == MatchType::Synonym;
+                if expanded && container.match_type > *kind {
+                    container.match_type = *kind;
+                }
                 // This is an exact result if
-                //   1. The result token matches the query token OR this is the first result token
-                //   2. The token length is greater than 1
-                if (*result == *token || mid == 0) && token.len() > 1 {
+                //   1. The result token matches the query token OR this is the first result
+                //      token (of the prefix results when typos are mixed in) of a token
+                //      which may be incomplete
+                //   2. The token is longer than one character
+                let first =
+                    mid == 0 && (*kind == MatchType::Typo) == is_typo && !expanded && !complete;
+                if (*result == *token || first) && token.chars().count() > 1 {
                     container.match_type = MatchType::Exact;
                 }
             }
         }
 
-        found_indices
+        (found_indices, corrections)
+    }
+
+    /// Looks up the indexed words of each phrase in the query, returns `None` if
+    /// any phrase contains a word which is not in the index (nothing can match)
+    fn resolve_phrases(&self, query: &Query) -> Option<Vec<Vec<PhraseEntry<'_>>>> {
+        query
+            .phrases
+            .iter()
+            .map(|phrase| {
+                query.tokens[phrase.clone()]
+                    .iter()
+                    .enumerate()
+                    // Dropped stop words are not indexed, they only take up a position
+                    .filter(|(_, t)| !self.stop_words.is_dropped(&t.token))
+                    .map(|(offset, t)| {
+                        self.fst_map.get(&t.token).map(|rid| PhraseEntry {
+                            entry: &self.reverse_index[rid as usize],
+                            offset,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            // Phrases made up entirely of stop words cannot be checked
+            .filter(|entries| !matches!(entries, Some(e) if e.is_empty()))
+            .collect()
+    }
+
+    /// Resolves the phrases, required clauses and excluded words of the query,
+    /// returns `None` if a phrase or required clause cannot match anything
+    fn resolve_constraints<'a>(
+        &'a self,
+        query: &Query,
+        found_indices: &HashMap<u64, ReverseIndexEntryWithMatch<'a>>,
+    ) -> Option<Constraints<'a>> {
+        let phrases = self.resolve_phrases(query)?;
+        let required = query
+            .clauses_with(Occur::Must)
+            .map(|c| {
+                let entries: Vec<_> = found_indices
+                    .values()
+                    .filter(|f| f.qidx == c)
+                    .map(|f| f.entry)
+                    .collect();
+                if entries.is_empty() {
+                    None
+                } else {
+                    Some(entries)
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
+        // Only the excluded words themselves are excluded, not words they prefix
+        let excluded = query
+            .clauses_with(Occur::MustNot)
+            .flat_map(|c| query.clauses[c].tokens.iter())
+            .filter_map(|t| self.fst_map.get(&query.tokens[*t].token))
+            .map(|rid| &self.reverse_index[rid as usize])
+            .collect();
+
+        Some(Constraints {
+            phrases,
+            required,
+            excluded,
+        })
+    }
+
+    /// Determines whether the words of a phrase appear contiguously and in order
+    /// in the given verse and translation
+    fn phrase_matches(phrase: &[PhraseEntry], verse_key: &[u8], translation: usize) -> bool {
+        let positions: Option<Vec<_>> = phrase
+            .iter()
+            .map(|p| p.entry.get_positions(verse_key, translation))
+            .collect();
+        let positions = match positions {
+            Some(positions) => positions,
+            None => return false,
+        };
+        // Every occurrence of the first word is a potential start of the phrase
+        positions[0].iter().any(|first| {
+            let start = *first as usize;
+            start >= phrase[0].offset
+                && phrase.iter().zip(positions.iter()).all(|(p, pos)| {
+                    let expected = start - phrase[0].offset + p.offset;
+                    pos.binary_search(&(expected as u16)).is_ok()
+                })
+        })
     }
 
     #[inline]
     fn score_results(
         &self,
         found_indices: &HashMap<u64, ReverseIndexEntryWithMatch>,
+        constraints: &Constraints,
+        key_ranges: Option<&[KeyRange]>,
+        translations: &[bool],
     ) -> HashMap<Vec<u8>, VerseMatch> {
-        let mut priority_lists: Vec<_> = found_indices.values().collect();
-        priority_lists.sort_by(|a, b| {
-            if a.match_type != b.match_type {
-                // Prefer exact matches
-                a.match_type.cmp(&b.match_type)
-            } else {
-                // Order by matches ascending
-                a.entry.len().cmp(&b.entry.len())
-            }
-        });
-        // Pick a token list to use as result candidates
-        let candidates_list = priority_lists
+        // Every result must contain all phrases and one word of each required clause
+        // so the least common phrase word or required clause gives the smallest
+        // complete candidates
+        let complete_lists = constraints
+            .phrases
             .iter()
-            // First, try to find a list with >= 3x max results
-            .find(|l| l.entry.len() >= MAX_RESULTS * 3)
-            .unwrap_or_else(|| {
-                priority_lists
-                    .iter()
-                    // Second, try to find a list with >= 2x max results
-                    .find(|l| l.entry.len() >= MAX_RESULTS * 2)
-                    .unwrap_or_else(|| {
-                        priority_lists
-                            .iter()
-                            // Third, try to find a list with >= max results
-                            .find(|l| l.entry.len() >= MAX_RESULTS)
-                            // Fall back to just taking the list with the most results
-                            .unwrap_or_else(|| priority_lists.last().unwrap())
-                    })
-            });
-        // Construct empty scores map with each candidate verse
-        let mut result_scores = HashMap::with_capacity(candidates_list.entry.len());
-        for key_bytes in candidates_list.entry.get_verse_keys() {
+            .flatten()
+            .map(|p| vec![p.entry])
+            .chain(constraints.required.iter().cloned())
+            .min_by_key(|entries| entries.iter().map(|e| e.len_in(key_ranges)).sum::<usize>());
+        // Otherwise a verse matching any word (or any alternative of a clause) is a
+        // candidate
+        let candidates_lists =
+            complete_lists.unwrap_or_else(|| found_indices.values().map(|f| f.entry).collect());
+        // Construct empty scores map with each candidate verse (within the filters)
+        let mut result_scores = HashMap::new();
+        for key_bytes in candidates_lists
+            .iter()
+            .flat_map(|l| l.get_verse_keys_in(key_ranges))
+        {
             let key = VerseKey::from_be_bytes(&key_bytes);
-            result_scores.insert(
-                key_bytes.clone(),
-                VerseMatch::new(key, self.verse_popularity.get(key_bytes).map_or(0, |v| v)),
-            );
+            let popularity = self.verse_popularity.get(&key_bytes).map_or(0, |v| v);
+            result_scores.insert(key_bytes, VerseMatch::new(key, popularity, translations));
         }
 
+        // Proximity only matters when there are multiple query words
+        let track_proximity = found_indices.values().map(|f| f.qidx).unique().count() > 1;
+
+        // Rare words weigh more than common words in relevance
+        let total_verses = self.verse_popularity.len();
+        let idfs: HashMap<u64, f64> = found_indices
+            .iter()
+            .map(|(rid, f)| (*rid, idf(f.entry.len(), total_verses)))
+            .collect();
+
         // Loop over each candidate verse for scoring
         for (result_key, result_match) in result_scores.iter_mut() {
+            // Translations which were not requested or do not meet the constraints
+            // (phrases, required and excluded words) are not considered
+            let matched_translations: Vec<bool> = (0..TRANSLATION_COUNT)
+                .map(|i| translations[i] && constraints.matches(result_key, i))
+                .collect();
+            // Translation id => Query word => Relevance of its best matching token
+            let mut relevance = vec![HashMap::new(); TRANSLATION_COUNT];
+
             // Loop over each found index entry (query word) from the previous step
-            for ReverseIndexEntryWithMatch {
-                match_type,
-                entry,
-                qidx,
-                ..
-            } in found_indices.values()
+            for (
+                rid,
+                ReverseIndexEntryWithMatch {
+                    match_type,
+                    entry,
+                    qidx,
+                    ..
+                },
+            ) in found_indices.iter()
             {
                 // Does this found entry match the current verse?
+                let mut matched = false;
                 if let Some(found_counts) = entry.get_counts(&result_key) {
                     for (i, count) in found_counts.iter().enumerate() {
                         // Does the found entry match the current translation?
-                        if *count > 0 {
+                        if *count > 0 && matched_translations[i] {
+                            matched = true;
                             // Increment words matched
                             result_match.inc_query_words(i, *qidx);
+                            // Track where the word appears for proximity
+                            if track_proximity {
+                                if let Some(positions) = entry.get_positions(result_key, i) {
+                                    result_match.add_positions(i, *qidx, &positions);
+                                }
+                            }
+                            // Keep the relevance of the best matching token of each
+                            // query word so expansions do not add up
+                            let score = bm25(
+                                idfs[rid],
+                                *count,
+                                self.verse_length(i, result_key),
+                                self.average_verse_lengths[i],
+                            );
+                            let best = relevance[i].entry(*qidx).or_insert(0.0);
+                            if score > *best {
+                                *best = score;
+                            }
                             // Increment exact/typo matches if necessary
                             match *match_type {
                                 MatchType::Exact => result_match.inc_exact(i),
+                                MatchType::Stemmed => result_match.inc_stemmed(i),
+                                MatchType::Synonym => result_match.inc_synonyms(i),
                                 MatchType::Typo => result_match.inc_typos(i),
                                 _ => {}
                             }
@@ -197,109 +617,1015 @@ impl VersearchIndex {
                     }
                 }
 
-                // Track words to highlight for this result
-                if let Some(found_highlights) = entry.get_highlights(result_key) {
-                    result_match.extend_highlights(found_highlights);
+                // Track words to highlight for this result, unless they only appear
+                // in translations which were not requested or matched
+                if matched {
+                    if let Some(found_highlights) = entry.get_highlights(result_key) {
+                        result_match.extend_highlights(found_highlights);
+                    }
                 }
             }
+
+            if track_proximity {
+                result_match.compute_proximity();
+            }
+            result_match.set_relevance(
+                relevance
+                    .iter()
+                    .map(|scores| scores.values().sum())
+                    .fold(0.0, f64::max),
+            );
+        }
+
+        // Drop verses where no requested translation contains the query (or meets
+        // the constraints)
+        if !constraints.is_empty() || translations.contains(&false) {
+            result_scores.retain(|_, m| m.has_query_words());
         }
 
         // Done scoring!
         result_scores
     }
 
+    /// Returns the number of tokens in a verse (given as key bytes) of a
+    /// translation, or the average if it is not known
+    fn verse_length(&self, translation: usize, verse_key: &[u8]) -> f64 {
+        let mut key = vec![translation as u8];
+        key.extend(verse_key);
+        self.translation_verses_map
+            .get(&key)
+            .and_then(|idx| self.translation_verse_lengths.get(idx as usize))
+            .map_or(self.average_verse_lengths[translation], |length| {
+                *length as f64
+            })
+    }
+
     #[inline]
-    fn collect_results(&self, results_map: &HashMap<Vec<u8>, VerseMatch>) -> Vec<VerseResult> {
+    fn collect_results(
+        &self,
+        results_map: &HashMap<Vec<u8>, VerseMatch>,
+        rules: &RankingRules,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<VerseResult> {
         results_map
             .values()
-            .sorted_by(|r1, r2| r1.cmp(r2))
-            .take(MAX_RESULTS)
-            .map(|r| VerseResult {
-                key: Some(r.key),
-                top_translation: r.top_translation(),
-                text: (0..TRANSLATION_COUNT)
-                    .map(|i| {
-                        let key = translation_verses_bytes_key(i as u8, &r.key);
-                        self.translation_verses_map.get(key).map_or_else(
-                            || "".to_string(),
-                            |idx| {
-                                self.translation_verses_strings
-                                    .get(idx as usize)
-                                    .map_or_else(|| "".to_string(), |s| s.clone())
-                            },
-                        )
-                    })
-                    .collect(),
-                highlights: r
-                    .highlights
+            .sorted_by(|r1, r2| rules.cmp_verses(r1, r2))
+            .skip(offset)
+            .take(limit)
+            .map(|r| self.verse_result(r, rules))
+            .collect()
+    }
+
+    /// Returns the text of a verse in the given translation, or an empty string if
+    /// the translation does not contain the verse
+    fn verse_text(&self, translation: usize, key: &VerseKey) -> String {
+        let key = translation_verses_bytes_key(translation as u8, key);
+        self.translation_verses_map.get(key).map_or_else(
+            || "".to_string(),
+            |idx| {
+                self.translation_verses_strings
+                    .get(idx as usize)
+                    .map_or_else(|| "".to_string(), |s| s.clone())
+            },
+        )
+    }
+
+    /// Converts a scored verse into a service result, filling in text for every
+    /// requested translation
+    fn verse_result(&self, r: &VerseMatch, rules: &RankingRules) -> VerseResult {
+        VerseResult {
+            key: Some(r.key),
+            top_translation: r.top_translation(rules),
+            text: (0..TRANSLATION_COUNT)
+                .map(|i| {
+                    if !r.includes_translation(i) {
+                        return "".to_string();
+                    }
+                    self.verse_text(i, &r.key)
+                })
+                .collect(),
+            highlights: r
+                .highlights
+                .iter()
+                .map(|i| {
+                    self.highlight_words
+                        .get(*i as usize)
+                        .expect("Invalid highlight word index")
+                })
+                .cloned()
+                .collect(),
+            rankings: r.to_service_rankings(),
+            popularity: r.popularity as i32,
+            context: Vec::new(),
+            highlight_spans: Vec::new(),
+            snippets: Vec::new(),
+        }
+    }
+
+    /// Fills in the offsets of the matched words in the text of each translation
+    /// which matched the query, and snippets around them if `snippet_length` is
+    /// not 0
+    fn add_highlight_spans(
+        &self,
+        results: &mut [VerseResult],
+        found_indices: &HashMap<u64, ReverseIndexEntryWithMatch>,
+        utf16: bool,
+        snippet_length: usize,
+    ) {
+        let to_spans = |text: &str, ranges: &[Range<usize>]| {
+            let offset = |byte: usize| {
+                if utf16 {
+                    text[..byte].encode_utf16().count() as u32
+                } else {
+                    byte as u32
+                }
+            };
+            ranges
+                .iter()
+                .map(|range| Span {
+                    start: offset(range.start),
+                    end: offset(range.end),
+                })
+                .collect()
+        };
+        for result in results.iter_mut() {
+            let key_bytes = match result.key {
+                Some(key) => key.to_be_bytes(),
+                None => continue,
+            };
+            // Byte ranges of the matched words in each translation
+            let matches: Vec<Vec<Range<usize>>> = result
+                .text
+                .iter()
+                .zip(result.rankings.iter())
+                .enumerate()
+                .map(|(i, (text, ranking))| {
+                    if ranking.query_words == 0 {
+                        return Vec::new();
+                    }
+                    // Positions count the words of the text which produce tokens
+                    let positions: BTreeSet<u16> = found_indices
+                        .values()
+                        .filter_map(|f| f.entry.get_positions(&key_bytes, i))
+                        .flatten()
+                        .collect();
+                    self.tokenizers[i]
+                        .token_offsets(text)
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(position, _)| positions.contains(&(*position as u16)))
+                        .map(|(_, range)| range)
+                        .collect()
+                })
+                .collect();
+            result.highlight_spans = result
+                .text
+                .iter()
+                .zip(matches.iter())
+                .map(|(text, ranges)| Spans {
+                    spans: to_spans(text, ranges),
+                })
+                .collect();
+            if snippet_length > 0 {
+                result.snippets = result
+                    .text
                     .iter()
-                    .map(|i| {
-                        self.highlight_words
-                            .get(*i as usize)
-                            .expect("Invalid highlight word index")
+                    .zip(matches.iter())
+                    .map(|(text, ranges)| {
+                        let (text, ranges) = snippet(text, ranges, snippet_length);
+                        Snippet {
+                            spans: to_spans(&text, &ranges),
+                            text,
+                        }
                     })
-                    .cloned()
-                    .collect(),
-                rankings: r.to_service_rankings(),
-                popularity: r.popularity as i32,
-            })
+                    .collect();
+            }
+        }
+    }
+
+    /// Returns the keys of the verses in a translation between two verses of the
+    /// same chapter (exclusive)
+    fn translation_verse_keys_between(
+        &self,
+        translation: usize,
+        start: &VerseKey,
+        end: &VerseKey,
+    ) -> Vec<VerseKey> {
+        self.translation_verses_map
+            .range()
+            .gt(translation_verses_bytes_key(translation as u8, start))
+            .lt(translation_verses_bytes_key(translation as u8, end))
+            .into_stream()
+            .into_byte_keys()
+            .iter()
+            // Skip the translation byte
+            .map(|bytes| VerseKey::from_be_bytes(&bytes[1..]))
             .collect()
     }
 
+    /// Fills in up to `size` verses before and after each result from the same
+    /// chapter as context, with text for every requested translation
+    fn add_context(&self, results: &mut [VerseResult], size: usize, translations: &[bool]) {
+        if size == 0 {
+            return;
+        }
+        for result in results.iter_mut() {
+            let key = match result.key {
+                Some(key) => key,
+                None => continue,
+            };
+            let chapter_start = VerseKey { verse: 0, ..key };
+            let chapter_end = VerseKey {
+                verse: u8::MAX as u32,
+                ..key
+            };
+            // Translations may number verses differently so collect from all of them
+            let mut before = BTreeSet::new();
+            let mut after = BTreeSet::new();
+            for i in (0..TRANSLATION_COUNT).filter(|i| translations[*i]) {
+                before.extend(self.translation_verse_keys_between(i, &chapter_start, &key));
+                after.extend(self.translation_verse_keys_between(i, &key, &chapter_end));
+            }
+            let keys = before
+                .iter()
+                .rev()
+                .take(size)
+                .rev()
+                .chain(after.iter().take(size));
+            result.context = keys
+                .map(|context_key| Context {
+                    key: Some(*context_key),
+                    text: (0..TRANSLATION_COUNT)
+                        .map(|i| {
+                            if translations[i] {
+                                self.verse_text(i, context_key)
+                            } else {
+                                "".to_string()
+                            }
+                        })
+                        .collect(),
+                })
+                .collect();
+        }
+    }
+
+    /// Returns the keys of all verses in the inclusive range, in canonical order
+    fn verse_keys_in(&self, start: &VerseKey, end: &VerseKey) -> Vec<VerseKey> {
+        // Every verse has an entry in the popularity map, so it doubles as a verse listing
+        self.verse_popularity
+            .range()
+            .ge(start.to_be_bytes())
+            .le(end.to_be_bytes())
+            .into_stream()
+            .into_byte_keys()
+            .iter()
+            .map(|bytes| VerseKey::from_be_bytes(bytes))
+            .collect()
+    }
+
+    /// Converts a verse which was not matched by a query into a service result
+    fn plain_verse_result(&self, key: VerseKey, translations: &[bool]) -> VerseResult {
+        let popularity = self
+            .verse_popularity
+            .get(key.to_be_bytes())
+            .map_or(0, |v| v);
+        self.verse_result(
+            &VerseMatch::new(key, popularity, translations),
+            &self.config.ranking_rules,
+        )
+    }
+
+    /// Collects a page of results for a reference search, returns `None` if the
+    /// reference does not resolve to any verses (e.g., "John 99")
+    fn search_reference(
+        &self,
+        reference: &Reference,
+        translations: &[bool],
+        offset: usize,
+        limit: usize,
+    ) -> Option<ServiceResponse> {
+        let (start, end) = reference.key_range();
+        let keys = self.verse_keys_in(&start, &end);
+        if keys.is_empty() {
+            return None;
+        }
+        Some(ServiceResponse {
+            total: keys.len() as i32,
+            results: keys
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .map(|key| self.plain_verse_result(key, translations))
+                .collect(),
+            timings: None,
+            suggestion: String::new(),
+        })
+    }
+
+    /// Returns a single verse in every translation, or `None` if it does not exist
+    pub fn get_verse(&self, key: &VerseKey) -> Option<VerseResult> {
+        if !key.fits_bytes() {
+            return None;
+        }
+        self.verse_popularity.get(key.to_be_bytes())?;
+        Some(self.plain_verse_result(*key, &vec![true; TRANSLATION_COUNT]))
+    }
+
+    /// Returns every verse in the inclusive range in every translation, in
+    /// canonical order, or nothing if either end is not a valid verse key
+    pub fn get_range(&self, start: &VerseKey, end: &VerseKey) -> Vec<VerseResult> {
+        if !start.fits_bytes() || !end.fits_bytes() {
+            return Vec::new();
+        }
+        self.verse_keys_in(start, end)
+            .into_iter()
+            .map(|key| self.plain_verse_result(key, &vec![true; TRANSLATION_COUNT]))
+            .collect()
+    }
+
+    /// Returns every verse of a chapter in every translation, in canonical order
+    pub fn get_chapter(&self, book: Book, chapter: u32) -> Vec<VerseResult> {
+        let start = VerseKey {
+            book: book as i32,
+            chapter,
+            verse: 0,
+        };
+        let end = VerseKey {
+            verse: u8::MAX as u32,
+            ..start
+        };
+        self.get_range(&start, &end)
+    }
+
     /// Perform a search against the index
     pub fn search(&self, text: &str) -> ServiceResponse {
+        self.search_with_options(text, &SearchOptions::default())
+    }
+
+    /// Completes the partially typed last word of the text with up to `n` of the
+    /// most common words in the index starting with it (e.g., "righ" to
+    /// "righteous", "righteousness" and "right")
+    pub fn complete(&self, text: &str, n: usize) -> Vec<String> {
+        let limit = match n {
+            0 => DEFAULT_COMPLETIONS,
+            n => n.min(MAX_LIMIT),
+        };
+        // Nothing is being typed after a space
+        if text.ends_with(char::is_whitespace) {
+            return Vec::new();
+        }
+        let tokenizer =
+            self.query_tokenizer(&SearchOptions::default(), &vec![true; TRANSLATION_COUNT]);
+        let prefix = match tokenizer.tokenize(text).pop() {
+            Some(Tokenized { token, .. }) => token,
+            None => return Vec::new(),
+        };
+        let mut words: Vec<_> = self
+            .fst_map
+            .search(automaton::Str::new(&prefix).starts_with())
+            .into_stream()
+            .into_str_vec()
+            .unwrap()
+            .into_iter()
+            // Stop words only matter in phrases
+            .filter(|(word, _)| !self.stop_words.is_phrase_only(word))
+            .map(|(word, rid)| (self.reverse_index[rid as usize].len(), word))
+            .collect();
+        // Most common first, then alphabetically
+        words.sort_by(|(a_len, a), (b_len, b)| b_len.cmp(a_len).then_with(|| a.cmp(b)));
+        words
+            .into_iter()
+            .take(limit)
+            .map(|(_, word)| word.to_lowercase())
+            .collect()
+    }
+
+    /// Returns the tokenizer named in the options, or the tokenizer of the first
+    /// searched translation so queries are tokenized the same way it was indexed
+    fn query_tokenizer(
+        &self,
+        options: &SearchOptions,
+        translations: &[bool],
+    ) -> &'static dyn Tokenizer {
+        tokenizer_by_name(&options.tokenizer).unwrap_or_else(|| {
+            translations
+                .iter()
+                .position(|included| *included)
+                .map_or(&ENGLISH, |i| self.tokenizers[i])
+        })
+    }
+
+    /// Returns the ranking rules named in the options, or the rules of the index
+    fn ranking_rules(&self, options: &SearchOptions) -> RankingRules {
+        RankingRules::from_names(&options.ranking_rules)
+            .unwrap_or_else(|| self.config.ranking_rules.clone())
+    }
+
+    /// Perform a search against the index, restricting results to the books,
+    /// ranges and translations given in the options. Book and range filters do
+    /// not apply to references, which already name the verses to return.
+    pub fn search_with_options(&self, text: &str, options: &SearchOptions) -> ServiceResponse {
+        let mut timer = PhaseTimer::start(self.clock);
+        let translations: Vec<bool> = (0..TRANSLATION_COUNT)
+            .map(|i| options.includes_translation(i))
+            .collect();
+        // Offsets past the last verse return nothing anyway
+        let offset = (options.offset as usize).min(self.verse_popularity.len());
+        let limit = match options.limit as usize {
+            0 => DEFAULT_LIMIT,
+            limit => limit.min(MAX_LIMIT),
+        };
+        let context = (options.context as usize).min(MAX_CONTEXT);
+
+        // References (e.g., "John 3:16") resolve directly to verses, unless only a
+        // suggestion is wanted
+        if let Some(mut response) = Some(text)
+            .filter(|_| !options.suggest_only())
+            .and_then(parse_reference)
+            .and_then(|r| self.search_reference(&r, &translations, offset, limit))
+        {
+            // Nothing is highlighted but snippets may still be wanted for long verses
+            self.add_highlight_spans(
+                &mut response.results,
+                &HashMap::new(),
+                options.utf16_offsets(),
+                options.snippet_length as usize,
+            );
+            self.add_context(&mut response.results, context, &translations);
+            return ServiceResponse {
+                timings: timer.finish(),
+                ..response
+            };
+        }
+
         // Tokenize input text
-        // See comment on wasm_timer above
-        // let start = Instant::now();
-        let tokens = tokenize(text);
-        // let tokenize_us = start.elapsed().as_micros() as i32;
+        let tokenizer = self.query_tokenizer(options, &translations);
+        let query = parse_query(text, tokenizer);
+        timer.tokenize();
 
         // If we have no tokens (empty search), bail
-        if tokens.is_empty() {
+        if query.tokens.is_empty() {
             return ServiceResponse {
-                results: Vec::new(),
-                timings: None,
+                timings: timer.finish(),
+                ..Default::default()
             };
         }
 
         // Expand and determine score multiplier for each token
-        // See comment on wasm_timer above
-        // let start = Instant::now();
-        let found_indices = self.traverse_fst(&tokens);
-        // let fst_us = start.elapsed().as_micros() as i32;
-
-        // If we found no index entries (no valid words), bail
-        if found_indices.is_empty() {
+        let (found_indices, corrections) = self.traverse_fst(&query, options.as_you_type());
+        let suggestion = if corrections.is_empty() {
+            String::new()
+        } else {
+            query.replace_words(text, &corrections)
+        };
+        if options.suggest_only() {
+            timer.fst();
             return ServiceResponse {
-                results: Vec::new(),
-                timings: None,
+                suggestion,
+                timings: timer.finish(),
+                ..Default::default()
             };
         }
+        let constraints = self.resolve_constraints(&query, &found_indices);
+        timer.fst();
+
+        // If we found no index entries (no valid words) or a phrase or required clause
+        // cannot match, bail
+        let constraints = match constraints {
+            Some(constraints) if !found_indices.is_empty() => constraints,
+            _ => {
+                return ServiceResponse {
+                    suggestion,
+                    timings: timer.finish(),
+                    ..Default::default()
+                }
+            }
+        };
 
         // Score all results
-        // See comment on wasm_timer above
-        // let start = Instant::now();
-        let result_scores = self.score_results(&found_indices);
-        // let score_us = start.elapsed().as_micros() as i32;
+        let key_ranges = options.key_ranges();
+        let result_scores = self.score_results(
+            &found_indices,
+            &constraints,
+            key_ranges.as_deref(),
+            &translations,
+        );
+        timer.score();
 
         // Collect ranked results
-        // See comment on wasm_timer above
-        // let start = Instant::now();
-        let results = self.collect_results(&result_scores);
-        // let rank_us = start.elapsed().as_micros() as i32;
+        let rules = self.ranking_rules(options);
+        let mut results = self.collect_results(&result_scores, &rules, offset, limit);
+        self.add_highlight_spans(
+            &mut results,
+            &found_indices,
+            options.utf16_offsets(),
+            options.snippet_length as usize,
+        );
+        self.add_context(&mut results, context, &translations);
+        timer.rank();
 
         // Construct and return response
         ServiceResponse {
             results,
-            timings: None,
-            // See comment on wasm_timer above
-            // timings: Some(Timings {
-            //     tokenize: tokenize_us,
-            //     fst: fst_us,
-            //     score: score_us,
-            //     rank: rank_us,
-            //     total: tokenize_us + fst_us + score_us + rank_us,
-            // }),
+            // Every scored verse can be reached by paging, so all of them count
+            total: result_scores.len() as i32,
+            timings: timer.finish(),
+            suggestion,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::data::{TranslationData, VerseText};
+    use proto::engine::SynonymGroup;
+    use proto::service::search_options;
+    use std::collections::BTreeSet;
+    use util::build_index_proto_struct;
+
+    fn verse(book: Book, chapter: u32, verse: u32, text: &str) -> VerseText {
+        VerseText {
+            key: Some(VerseKey {
+                book: book as i32,
+                chapter,
+                verse,
+            }),
+            text: text.to_string(),
+        }
+    }
+
+    /// Returns a few KJV verses
+    fn kjv() -> TranslationData {
+        TranslationData {
+            translation: Translation::Kjv as i32,
+            verses: vec![
+                verse(Book::Genesis, 1, 1, "In the beginning God created the heaven and the earth."),
+                verse(Book::Genesis, 1, 2, "And the earth was without form, and void; and darkness was upon the face of the deep."),
+                verse(Book::Genesis, 1, 3, "And God said, Let there be light: and there was light."),
+                verse(Book::Genesis, 50, 26, "So Joseph died, being an hundred and ten years old: and they embalmed him, and he was put in a coffin in Egypt."),
+                verse(Book::Exodus, 1, 1, "Now these are the names of the children of Israel, which came into Egypt; every man and his household came with Jacob."),
+                verse(Book::John, 3, 16, "For God so loved the world, that he gave his only begotten Son, that whosoever believeth in him should not perish, but have everlasting life."),
+                verse(Book::John, 3, 17, "For God sent not his Son into the world to condemn the world; but that the world through him might be saved."),
+                verse(Book::Romans, 3, 23, "For all have sinned, and come short of the glory of God;"),
+                verse(Book::FirstCorinthians, 13, 4, "Charity suffereth long, and is kind; charity envieth not; charity vaunteth not itself, is not puffed up,"),
+                verse(Book::Ephesians, 2, 8, "For by grace are ye saved through faith; and that not of yourselves: it is the gift of God:"),
+                verse(Book::Jude, 1, 5, "I will therefore put you in remembrance, though ye once knew this, how that the Lord, having saved the people out of the land of Egypt, afterward destroyed them that believed not."),
+            ],
+        }
+    }
+
+    /// Builds an index of a few KJV verses
+    fn index() -> VersearchIndex {
+        index_of(&[kjv()], StopWordMode::Drop)
+    }
+
+    /// Builds an index of the given translations
+    fn index_of(translations: &[TranslationData], stop_word_mode: StopWordMode) -> VersearchIndex {
+        let stop_words = StopWords::new(
+            util::STOP_WORDS.iter().map(|w| w.to_string()).collect(),
+            stop_word_mode,
+        );
+        let visits = [VerseKey {
+            book: Book::John as i32,
+            chapter: 3,
+            verse: 16,
+        }];
+        let synonyms = vec![SynonymGroup {
+            words: vec!["THEE".to_string(), "YOU".to_string()],
+        }];
+        let data = build_index_proto_struct(translations, &visits, stop_words, synonyms, true);
+        VersearchIndex::from_index_data_proto_struct(data)
+    }
+
+    #[test]
+    fn test_pagination() {
+        let index = index();
+        let page = |offset: u32| {
+            index.search_with_options(
+                "god saved",
+                &SearchOptions {
+                    offset,
+                    limit: 1,
+                    ..Default::default()
+                },
+            )
+        };
+        let total = page(0).total;
+        assert!(total > 1);
+        let mut seen = BTreeSet::new();
+        for offset in 0..total as u32 {
+            let response = page(offset);
+            assert_eq!(response.total, total);
+            assert_eq!(response.results.len(), 1);
+            assert!(seen.insert(response.results[0].key), "offset {}", offset);
+        }
+        assert!(page(total as u32).results.is_empty());
+        assert!(page(u32::MAX).results.is_empty());
+    }
+
+    /// Returns the most query words any translation of a result matched
+    fn query_words(response: &ServiceResponse) -> Vec<i32> {
+        response
+            .results
+            .iter()
+            .map(|r| r.rankings.iter().map(|r| r.query_words).max().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_prefix_last_token_only() {
+        let mut index = index();
+        // Every token may be a prefix by default
+        let response = index.search("beginn ear");
+        assert_eq!(query_words(&response), vec![2, 1]);
+
+        // Searching as you type, only the last token is a prefix
+        let as_you_type = SearchOptions {
+            mode: search_options::Mode::AsYouType as i32,
+            ..Default::default()
+        };
+        let response = index.search_with_options("beginn ear", &as_you_type);
+        assert_eq!(query_words(&response), vec![1, 1]);
+        let response = index.search_with_options("beginning ear", &as_you_type);
+        assert_eq!(query_words(&response), vec![2, 1]);
+
+        // The same applies to every search when configured
+        index.set_config(SearchConfig {
+            prefix_last_token_only: true,
+            ..SearchConfig::default()
+        });
+        let response = index.search("beginn ear");
+        assert_eq!(query_words(&response), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_get_out_of_range() {
+        let index = index();
+        let key = |chapter, verse| VerseKey {
+            book: Book::John as i32,
+            chapter,
+            verse,
+        };
+        assert!(index.get_verse(&key(3, 16)).is_some());
+        // 259 would wrap around to chapter 3 in a verse key
+        assert!(index.get_verse(&key(259, 16)).is_none());
+        assert!(index.get_verse(&key(3, 272)).is_none());
+        assert_eq!(index.get_chapter(Book::John, 3).len(), 2);
+        assert!(index.get_chapter(Book::John, 259).is_empty());
+        assert_eq!(index.get_range(&key(3, 0), &key(3, 255)).len(), 2);
+        assert!(index.get_range(&key(3, 0), &key(3, 256)).is_empty());
+        assert!(index.get_range(&key(u32::MAX, 0), &key(3, 255)).is_empty());
+    }
+
+    #[test]
+    fn test_add_context() {
+        let index = index();
+        let context = |text: &str, context: u32| {
+            let response = index.search_with_options(
+                text,
+                &SearchOptions {
+                    context,
+                    ..Default::default()
+                },
+            );
+            response.results[0]
+                .context
+                .iter()
+                .map(|c| {
+                    let key = c.key.unwrap();
+                    (
+                        key.chapter,
+                        key.verse,
+                        c.text[Translation::Kjv as usize].clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let gen_1_1 = (
+            1,
+            1,
+            "In the beginning God created the heaven and the earth.".to_string(),
+        );
+        let gen_1_3 = (
+            1,
+            3,
+            "And God said, Let there be light: and there was light.".to_string(),
+        );
+
+        assert!(context("Gen 1:2", 0).is_empty());
+        assert_eq!(
+            context("Gen 1:2", 1),
+            vec![gen_1_1.clone(), gen_1_3.clone()]
+        );
+        // Clamped at the start and end of the chapter
+        assert_eq!(
+            context("Gen 1:1", 5)
+                .into_iter()
+                .map(|(c, v, _)| (c, v))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (1, 3)]
+        );
+        assert_eq!(context("Gen 1:3", 5)[0], gen_1_1);
+        assert_eq!(context("Gen 1:3", 5).len(), 2);
+        // Context never crosses into the next chapter or book
+        assert!(context("Gen 50:26", 5).is_empty());
+        assert!(context("Exod 1:1", 5).is_empty());
+    }
+
+    #[test]
+    fn test_suggestion() {
+        let index = index();
+        let response = index.search("put you in remembrence");
+        assert_eq!(response.suggestion, "put you in remembrance");
+        // Words matching a synonym are not misspelled although they are not indexed
+        let response = index.search("put thee in remembrence");
+        assert_eq!(response.suggestion, "put thee in remembrance");
+        assert!(index.search("thee").suggestion.is_empty());
+    }
+
+    #[test]
+    fn test_complete() {
+        let index = index();
+        // Most common first ("saved" is in three verses, "so" and "son" in two),
+        // then alphabetically
+        assert_eq!(
+            index.complete("s", 0),
+            vec![
+                "saved",
+                "so",
+                "son",
+                "said",
+                "sent",
+                "short",
+                "should",
+                "sinned",
+                "suffereth"
+            ]
+        );
+        assert_eq!(index.complete("God so S", 3), vec!["saved", "so", "son"]);
+        assert_eq!(index.complete("lov", 1), vec!["loved"]);
+        // Nothing to complete
+        assert!(index.complete("", 5).is_empty());
+        assert!(index.complete("saved ", 5).is_empty());
+        assert!(index.complete("xyz", 5).is_empty());
+    }
+
+    /// Returns the book, chapter and verse of every result
+    fn keys(response: &ServiceResponse) -> Vec<(Book, u32, u32)> {
+        response
+            .results
+            .iter()
+            .map(|r| {
+                let key = r.key.unwrap();
+                (Book::from_i32(key.book).unwrap(), key.chapter, key.verse)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_phrase() {
+        let index = index();
+        // Every word appears in John 3:16 and 3:17 but only 3:16 has them in order
+        assert_eq!(
+            keys(&index.search(r#""god so loved""#)),
+            vec![(Book::John, 3, 16)]
+        );
+        assert!(keys(&index.search("god so loved")).contains(&(Book::John, 3, 17)));
+        assert!(index.search(r#""so god loved""#).results.is_empty());
+        assert!(index
+            .search(r#""god created the earth""#)
+            .results
+            .is_empty());
+        assert_eq!(
+            keys(&index.search(r#"the "god created" earth"#)),
+            vec![(Book::Genesis, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_highlights_of_requested_translations() {
+        let net = TranslationData {
+            translation: Translation::Net as i32,
+            verses: vec![verse(Book::John, 3, 16, "For this is the way God loved the world: He gave his one and only Son, so that everyone who believes in him will not perish but have eternal life.")],
+        };
+        let index = index_of(&[kjv(), net], StopWordMode::Drop);
+        let highlights = |translations: Vec<Translation>| {
+            let response = index.search_with_options(
+                "god way",
+                &SearchOptions {
+                    translations: translations.into_iter().map(|t| t as i32).collect(),
+                    ..Default::default()
+                },
+            );
+            let result = response
+                .results
+                .iter()
+                .find(|r| r.key.unwrap().verse == 16)
+                .unwrap();
+            let mut highlights = result.highlights.clone();
+            highlights.sort();
+            highlights
+        };
+        assert_eq!(highlights(vec![]), vec!["GOD", "WAY"]);
+        assert_eq!(highlights(vec![Translation::Net]), vec!["GOD", "WAY"]);
+        // Only the NET has "way"
+        assert_eq!(highlights(vec![Translation::Kjv]), vec!["GOD"]);
+    }
+
+    /// Returns the keys of every result of a search, in canonical order
+    fn sorted_keys(index: &VersearchIndex, text: &str) -> Vec<(Book, u32, u32)> {
+        let mut keys = keys(&index.search(text));
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_operators() {
+        let index = index();
+        let grace_or_charity = vec![(Book::FirstCorinthians, 13, 4), (Book::Ephesians, 2, 8)];
+        // Verses matching any alternative or optional word are results
+        assert_eq!(sorted_keys(&index, "grace OR charity"), grace_or_charity);
+        assert_eq!(sorted_keys(&index, "charity OR grace"), grace_or_charity);
+        assert_eq!(sorted_keys(&index, "charity grace"), grace_or_charity);
+        // Required words must appear, other words only rank
+        assert_eq!(
+            sorted_keys(&index, "+saved god"),
+            vec![
+                (Book::John, 3, 17),
+                (Book::Ephesians, 2, 8),
+                (Book::Jude, 1, 5)
+            ]
+        );
+        assert_eq!(
+            sorted_keys(&index, "+grace OR charity faith"),
+            grace_or_charity
+        );
+        // Excluded words must not appear
+        assert_eq!(
+            sorted_keys(&index, "saved -egypt"),
+            vec![(Book::John, 3, 17), (Book::Ephesians, 2, 8)]
+        );
+        assert_eq!(
+            sorted_keys(&index, "god -world"),
+            vec![
+                (Book::Genesis, 1, 1),
+                (Book::Genesis, 1, 3),
+                (Book::Romans, 3, 23),
+                (Book::Ephesians, 2, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_total_of_disjoint_words() {
+        let index = index();
+        // No verse matches more than one of the words
+        let text = "charity grace coffin";
+        assert_eq!(index.search(text).total, 3);
+        let pages: Vec<_> = (0..3)
+            .flat_map(|offset| {
+                let response = index.search_with_options(
+                    text,
+                    &SearchOptions {
+                        offset,
+                        limit: 1,
+                        ..Default::default()
+                    },
+                );
+                assert_eq!(response.total, 3);
+                keys(&response)
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            pages,
+            vec![
+                (Book::Genesis, 50, 26),
+                (Book::FirstCorinthians, 13, 4),
+                (Book::Ephesians, 2, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_token_typos() {
+        let mut index = index();
+        let long = "beginning".repeat(7);
+        assert!(index.search(&long).results.is_empty());
+        assert_eq!(
+            keys(&index.search(&format!("{} light", long))),
+            vec![(Book::Genesis, 1, 3)]
+        );
+        index.set_config(SearchConfig {
+            typos_with_prefix: true,
+            ..SearchConfig::default()
+        });
+        assert!(index.search(&long).results.is_empty());
+    }
+
+    #[test]
+    fn test_without_reversed_fst() {
+        let mut data = build_index_proto_struct(
+            &[kjv()],
+            &[],
+            StopWords::new(Default::default(), StopWordMode::Drop),
+            Vec::new(),
+            true,
+        );
+        data.reversed_fst = Vec::new();
+        let legacy = VersearchIndex::from_index_data_proto_struct(data);
+        // Suffix patterns still match without the reversed FST
+        assert_eq!(
+            sorted_keys(&legacy, "*rity"),
+            vec![(Book::FirstCorinthians, 13, 4)]
+        );
+        assert_eq!(sorted_keys(&legacy, "*ace"), sorted_keys(&index(), "*ace"));
+    }
+
+    #[test]
+    fn test_regex_diacritics() {
+        let index = index();
+        // Patterns are folded like indexed words
+        assert_eq!(
+            keys(&index.search("/^Créa.ed$/")),
+            vec![(Book::Genesis, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_highlight_spans() {
+        let kjv = TranslationData {
+            translation: Translation::Kjv as i32,
+            verses: vec![verse(
+                Book::SecondKings,
+                5,
+                19,
+                "𝄞 “Go in peace,” said Élisée.",
+            )],
+        };
+        let index = index_of(&[kjv], StopWordMode::Drop);
+        let spans = |offsets: search_options::Offsets| {
+            let response = index.search_with_options(
+                "élisée peace",
+                &SearchOptions {
+                    offsets: offsets as i32,
+                    ..Default::default()
+                },
+            );
+            response.results[0].highlight_spans[Translation::Kjv as usize]
+                .spans
+                .iter()
+                .map(|s| (s.start, s.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans(search_options::Offsets::Bytes),
+            vec![(14, 19), (29, 37)]
+        );
+        // The clef takes two UTF-16 code units, the accented letters and quotes one
+        assert_eq!(
+            spans(search_options::Offsets::Utf16),
+            vec![(10, 15), (23, 29)]
+        );
+    }
+
+    #[test]
+    fn test_single_character_not_exact() {
+        let kjv = TranslationData {
+            translation: Translation::Kjv as i32,
+            verses: vec![verse(
+                Book::Revelation,
+                1,
+                8,
+                "I am Α and Ω, the beginning and the ending",
+            )],
+        };
+        let index = index_of(&[kjv], StopWordMode::Drop);
+        let exact = |text: &str| index.search(text).results[0].rankings[0].exact;
+        assert_eq!(exact("beginning"), 1);
+        // A single letter is not an exact match, however many bytes it takes
+        assert_eq!(exact("i"), 0);
+        assert_eq!(exact("ω"), 0);
+    }
+
+    #[test]
+    fn test_phrase_only_stop_words() {
+        let index = index_of(&[kjv()], StopWordMode::PhraseOnly);
+        // "AND" starts with "AN" but stop words only match in phrases
+        assert_eq!(keys(&index.search("an")), vec![(Book::Genesis, 50, 26)]);
+        assert!(index.search("the").results.is_empty());
+        assert_eq!(
+            keys(&index.search(r#""in the beginning""#)),
+            vec![(Book::Genesis, 1, 1)]
+        );
+        // Stop words are kept in phrases, so they must be in the right places
+        assert!(index.search(r#""the god""#).results.is_empty());
+        assert!(index
+            .complete("an", 0)
+            .iter()
+            .all(|word| word.as_str() != "and"));
+    }
+}
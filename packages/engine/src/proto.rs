@@ -17,6 +17,14 @@ pub mod data {
             v
         }
 
+        /// Returns true if every part of the key fits in its byte, larger
+        /// numbers would wrap around to another verse
+        pub fn fits_bytes(&self) -> bool {
+            (0..=u8::MAX as i32).contains(&self.book)
+                && self.chapter <= u8::MAX as u32
+                && self.verse <= u8::MAX as u32
+        }
+
         pub fn from_be_bytes(bytes: &[u8]) -> Self {
             let book = u8::from_be_bytes([bytes[0]]) as i32;
             let chapter = u8::from_be_bytes([bytes[1]]) as u32;
@@ -103,6 +111,17 @@ pub mod data {
         }
     }
 
+    impl Translation {
+        pub fn from_string(name: &str) -> Result<Self> {
+            match name {
+                "KJV" => Ok(Self::Kjv),
+                "NET" => Ok(Self::Net),
+                "BSB" => Ok(Self::Bsb),
+                _ => Err(anyhow!("Invalid string for translation")),
+            }
+        }
+    }
+
     pub fn decode_translation_data(bytes: &[u8]) -> Result<TranslationData> {
         TranslationData::decode(bytes).context("Decoding Translation Data")
     }
@@ -128,7 +147,216 @@ pub mod data {
 }
 
 pub mod service {
+    use super::data::{Book, VerseKey};
+    use anyhow::{Context, Result};
+    use prost::Message;
+
     include!(concat!(env!("OUT_DIR"), "/instantbible.service.rs"));
+
+    /// An inclusive range of verse keys
+    pub type KeyRange = (VerseKey, VerseKey);
+
+    /// Returns a range covering every verse of the given books
+    pub fn book_range(first: Book, last: Book) -> KeyRange {
+        (
+            VerseKey {
+                book: first as i32,
+                chapter: 0,
+                verse: 0,
+            },
+            VerseKey {
+                book: last as i32,
+                chapter: u8::MAX as u32,
+                verse: u8::MAX as u32,
+            },
+        )
+    }
+
+    /// Intersects two sorted lists of ranges
+    fn intersect_ranges(a: &[KeyRange], b: &[KeyRange]) -> Vec<KeyRange> {
+        let mut out = Vec::new();
+        for (a_start, a_end) in a {
+            for (b_start, b_end) in b {
+                let start = std::cmp::max(a_start, b_start);
+                let end = std::cmp::min(a_end, b_end);
+                if start <= end {
+                    out.push((*start, *end));
+                }
+            }
+        }
+        out.sort();
+        out
+    }
+
+    impl SearchOptions {
+        /// Returns true if the given translation should be searched and returned
+        pub fn includes_translation(&self, idx: usize) -> bool {
+            self.translations.is_empty() || self.translations.contains(&(idx as i32))
+        }
+
+        /// Returns true if highlight spans should count UTF-16 code units
+        pub fn utf16_offsets(&self) -> bool {
+            self.offsets() == search_options::Offsets::Utf16
+        }
+
+        /// Returns true if only the last query token should match as a prefix
+        pub fn as_you_type(&self) -> bool {
+            self.mode() == search_options::Mode::AsYouType
+        }
+
+        /// Returns true if only a spelling suggestion is wanted, not results
+        pub fn suggest_only(&self) -> bool {
+            self.mode() == search_options::Mode::Suggest
+        }
+
+        /// Returns the ranges of verses allowed by the filters in these options
+        /// or `None` if every verse is allowed
+        pub fn key_ranges(&self) -> Option<Vec<KeyRange>> {
+            let mut groups = Vec::new();
+            if !self.books.is_empty() {
+                groups.push(
+                    self.books
+                        .iter()
+                        .filter_map(|b| Book::from_i32(*b))
+                        .map(|b| book_range(b, b))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            match search_options::Testament::from_i32(self.testament) {
+                Some(search_options::Testament::Old) => {
+                    groups.push(vec![book_range(Book::Genesis, Book::Malachi)])
+                }
+                Some(search_options::Testament::New) => {
+                    groups.push(vec![book_range(Book::Matthew, Book::Revelation)])
+                }
+                _ => {}
+            }
+            if !self.ranges.is_empty() {
+                let mut ranges: Vec<_> = self
+                    .ranges
+                    .iter()
+                    .filter_map(|r| Some((r.start?, r.end?)))
+                    // Larger numbers would wrap around to other verses
+                    .filter(|(start, end)| start.fits_bytes() && end.fits_bytes())
+                    .collect();
+                ranges.sort();
+                groups.push(ranges);
+            }
+
+            // A verse must satisfy every kind of filter given
+            let mut groups = groups.into_iter();
+            let first = groups.next()?;
+            Some(groups.fold(first, |acc, g| intersect_ranges(&acc, &g)))
+        }
+    }
+
+    impl From<Vec<response::VerseResult>> for Response {
+        /// Wraps verses which were looked up rather than searched for
+        fn from(results: Vec<response::VerseResult>) -> Self {
+            Self {
+                total: results.len() as i32,
+                results,
+                timings: None,
+                suggestion: String::new(),
+            }
+        }
+    }
+
+    impl From<Vec<String>> for Completions {
+        fn from(words: Vec<String>) -> Self {
+            Self { words }
+        }
+    }
+
+    pub fn decode_search_options(bytes: &[u8]) -> Result<SearchOptions> {
+        SearchOptions::decode(bytes).context("Decoding Search Options")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn key(book: Book, chapter: u32, verse: u32) -> VerseKey {
+            VerseKey {
+                book: book as i32,
+                chapter,
+                verse,
+            }
+        }
+
+        fn range(start: VerseKey, end: VerseKey) -> search_options::Range {
+            search_options::Range {
+                start: Some(start),
+                end: Some(end),
+            }
+        }
+
+        #[test]
+        fn test_intersect_ranges() {
+            let a = [
+                book_range(Book::Genesis, Book::Exodus),
+                book_range(Book::John, Book::John),
+            ];
+            let b = [(key(Book::Exodus, 20, 1), key(Book::John, 3, 16))];
+            assert_eq!(
+                intersect_ranges(&a, &b),
+                vec![
+                    (key(Book::Exodus, 20, 1), key(Book::Exodus, 255, 255)),
+                    (key(Book::John, 0, 0), key(Book::John, 3, 16)),
+                ]
+            );
+            assert!(intersect_ranges(&a, &[book_range(Book::Romans, Book::Jude)]).is_empty());
+            assert!(intersect_ranges(&a, &[]).is_empty());
+        }
+
+        #[test]
+        fn test_key_ranges() {
+            assert_eq!(SearchOptions::default().key_ranges(), None);
+
+            // Every kind of filter given must be satisfied
+            let options = SearchOptions {
+                books: vec![Book::John as i32, Book::Romans as i32],
+                testament: search_options::Testament::New as i32,
+                ranges: vec![range(key(Book::Luke, 1, 1), key(Book::John, 3, 16))],
+                ..Default::default()
+            };
+            assert_eq!(
+                options.key_ranges(),
+                Some(vec![(key(Book::John, 0, 0), key(Book::John, 3, 16))])
+            );
+            let options = SearchOptions {
+                books: vec![Book::Genesis as i32],
+                testament: search_options::Testament::New as i32,
+                ..Default::default()
+            };
+            assert_eq!(options.key_ranges(), Some(vec![]));
+
+            // Ranges which do not fit in a verse key are rejected, not wrapped
+            let options = SearchOptions {
+                ranges: vec![
+                    range(key(Book::John, 3, 1), key(Book::John, 259, 1)),
+                    range(key(Book::Romans, 8, 28), key(Book::Romans, 8, 39)),
+                ],
+                ..Default::default()
+            };
+            assert_eq!(
+                options.key_ranges(),
+                Some(vec![(key(Book::Romans, 8, 28), key(Book::Romans, 8, 39))])
+            );
+            let options = SearchOptions {
+                ranges: vec![range(
+                    VerseKey {
+                        book: -1,
+                        chapter: 0,
+                        verse: 0,
+                    },
+                    key(Book::John, 3, 16),
+                )],
+                ..Default::default()
+            };
+            assert_eq!(options.key_ranges(), Some(vec![]));
+        }
+    }
 }
 
 pub mod engine {
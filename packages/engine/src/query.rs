@@ -0,0 +1,318 @@
+use crate::regex_token::{fold_pattern, regex_pattern};
+use crate::tokenizer::Tokenizer;
+use crate::util::Tokenized;
+use crate::wildcard::is_wildcard;
+use std::ops::Range;
+
+/// Whether a clause must, may or must not appear in results
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Occur {
+    Should,
+    Must,
+    MustNot,
+}
+
+/// One or more alternative words joined by `OR`
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Clause {
+    pub occur: Occur,
+    /// Indices into the tokens of the query
+    pub tokens: Vec<usize>,
+}
+
+/// A parsed search query
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct Query {
+    /// Every token in the query, in order
+    pub tokens: Vec<Tokenized>,
+    /// Quoted phrases, as ranges of indices into `tokens`
+    pub phrases: Vec<Range<usize>>,
+    /// Every token belongs to exactly one clause, in query order
+    pub clauses: Vec<Clause>,
+    /// The stem of each token, `None` for patterns or if the tokenizer does not stem
+    pub stems: Vec<Option<String>>,
+}
+
+impl Query {
+    /// Returns true if the token at the given index is part of a phrase
+    pub fn in_phrase(&self, qidx: usize) -> bool {
+        self.phrases.iter().any(|p| p.contains(&qidx))
+    }
+
+    /// Returns the index of the clause containing the token at the given index
+    pub fn clause_of(&self, qidx: usize) -> usize {
+        self.clauses
+            .iter()
+            .position(|c| c.tokens.contains(&qidx))
+            .unwrap()
+    }
+
+    /// Returns the query text with the words of the given tokens replaced (e.g.,
+    /// a misspelled word with its correction), keeping the case of the first
+    /// letter. Replacements must be ordered by token index.
+    pub fn replace_words(&self, text: &str, replacements: &[(usize, String)]) -> String {
+        let mut replaced = String::with_capacity(text.len());
+        let mut cursor = 0;
+        let mut replacements = replacements.iter().peekable();
+        for (tidx, Tokenized { source, .. }) in self.tokens.iter().enumerate() {
+            // Replacements of earlier words which could not be found are dropped
+            while matches!(replacements.peek(), Some((r, _)) if *r < tidx) {
+                replacements.next();
+            }
+            let start = match text[cursor..].find(source.as_str()) {
+                Some(offset) => cursor + offset,
+                None => continue,
+            };
+            let end = start + source.len();
+            if let Some((_, word)) = replacements.peek().filter(|(r, _)| *r == tidx) {
+                let word = word.to_lowercase();
+                replacements.next();
+                replaced.push_str(&text[cursor..start]);
+                match word.chars().next() {
+                    Some(first) if source.starts_with(char::is_uppercase) => {
+                        replaced.extend(first.to_uppercase());
+                        replaced.push_str(&word[first.len_utf8()..]);
+                    }
+                    _ => replaced.push_str(&word),
+                }
+            } else {
+                replaced.push_str(&text[cursor..end]);
+            }
+            cursor = end;
+        }
+        replaced.push_str(&text[cursor..]);
+        replaced
+    }
+
+    /// Returns the indices of the clauses with the given occurrence
+    pub fn clauses_with(&self, occur: Occur) -> impl Iterator<Item = usize> + '_ {
+        self.clauses
+            .iter()
+            .enumerate()
+            .filter(move |(_, c)| c.occur == occur)
+            .map(|(i, _)| i)
+    }
+}
+
+/// Tokenizes a single word (which may produce several tokens, e.g.,
+/// "well-pleased"), keeping regex tokens as they are and `*` and `?` wildcards.
+/// A trailing `?` is treated as a question mark rather than a wildcard.
+fn tokenize_word(word: &str, tokenizer: &dyn Tokenizer) -> Vec<Tokenized> {
+    if regex_pattern(word).is_some() {
+        return vec![Tokenized {
+            token: fold_pattern(word, |c| tokenizer.fold(c)),
+            source: word.to_string(),
+        }];
+    }
+    let word = word.trim_end_matches('?');
+    if !is_wildcard(word) {
+        return tokenizer.tokenize(word);
+    }
+    // A pattern made up only of wildcards would match every word
+    if !word.chars().any(char::is_alphanumeric) {
+        return Vec::new();
+    }
+    let source: String = word
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '*' || *c == '?')
+        .collect();
+    // Fold around the wildcards so patterns match tokens as they are indexed
+    let token = source
+        .chars()
+        .map(|c| match c {
+            '*' | '?' => c.to_string(),
+            _ => tokenizer.fold(&c.to_string()),
+        })
+        .collect();
+    vec![Tokenized { source, token }]
+}
+
+/// Parses query text into tokens, quoted phrases and clauses. Unterminated
+/// quotes are treated as plain words so phrases do not kick in while the user
+/// is typing.
+///
+/// Words prefixed with `+` are required and words prefixed with `-` are
+/// excluded, words joined by `OR` match if any of them match. Any other word
+/// is optional, as are words in phrases (the phrase as a whole is required).
+/// Words outside of phrases may contain `*` and `?` wildcards or be regular
+/// expressions between slashes (e.g., `/bless(ed|ing)/`).
+pub fn parse_query(text: &str, tokenizer: &dyn Tokenizer) -> Query {
+    let mut tokens = Vec::new();
+    let mut phrases = Vec::new();
+    let mut clauses: Vec<Clause> = Vec::new();
+    let segments: Vec<_> = text.split('"').collect();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let start = tokens.len();
+        // Odd segments are between quotes, as long as a closing quote follows
+        let quoted = i % 2 == 1 && i < segments.len() - 1;
+        if quoted {
+            for token in tokenizer.tokenize(segment) {
+                clauses.push(Clause {
+                    occur: Occur::Should,
+                    tokens: vec![tokens.len()],
+                });
+                tokens.push(token);
+            }
+            if tokens.len() - start > 1 {
+                phrases.push(start..tokens.len());
+            }
+            continue;
+        }
+
+        let mut or = false;
+        for word in segment.split_whitespace() {
+            // Only join words in this segment, not the words of a phrase
+            if word == "OR" && tokens.len() > start {
+                or = true;
+                continue;
+            }
+            let (occur, word) = if let Some(word) = word.strip_prefix('+') {
+                (Occur::Must, word)
+            } else if let Some(word) = word.strip_prefix('-') {
+                (Occur::MustNot, word)
+            } else {
+                (Occur::Should, word)
+            };
+            let word_tokens = tokenize_word(word, tokenizer);
+            if word_tokens.is_empty() {
+                continue;
+            }
+            for (j, token) in word_tokens.into_iter().enumerate() {
+                // Only the first token of the word is an alternative to the previous
+                // clause, the rest follow it
+                match clauses.last_mut() {
+                    Some(clause) if or && j == 0 => {
+                        if clause.occur == Occur::Should {
+                            clause.occur = occur;
+                        }
+                        clause.tokens.push(tokens.len());
+                    }
+                    _ => clauses.push(Clause {
+                        occur,
+                        tokens: vec![tokens.len()],
+                    }),
+                }
+                tokens.push(token);
+            }
+            or = false;
+        }
+    }
+
+    let stems = tokens
+        .iter()
+        .map(|t| {
+            if regex_pattern(&t.token).is_some() || is_wildcard(&t.token) {
+                None
+            } else {
+                tokenizer.stem(&t.token)
+            }
+        })
+        .collect();
+
+    Query {
+        tokens,
+        phrases,
+        clauses,
+        stems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::ENGLISH;
+
+    fn tokens(query: &Query) -> Vec<&str> {
+        query.tokens.iter().map(|t| t.token.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query(r#"god "in the beginning" created"#, &ENGLISH);
+        assert_eq!(
+            tokens(&query),
+            vec!["GOD", "IN", "THE", "BEGINNING", "CREATED"]
+        );
+        assert_eq!(query.phrases, vec![1..4]);
+        assert!(query.in_phrase(3));
+        assert!(!query.in_phrase(4));
+
+        let query = parse_query(r#""for god" — "so loved""#, &ENGLISH);
+        assert_eq!(tokens(&query), vec!["FOR", "GOD", "SO", "LOVED"]);
+        assert_eq!(query.phrases, vec![0..2, 2..4]);
+
+        // Single words and unterminated quotes are not phrases
+        assert!(parse_query(r#""god" "so loved"#, &ENGLISH)
+            .phrases
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_query_operators() {
+        let query = parse_query("+grace -law faith OR hope", &ENGLISH);
+        assert_eq!(tokens(&query), vec!["GRACE", "LAW", "FAITH", "HOPE"]);
+        let occurs: Vec<_> = query.clauses.iter().map(|c| c.occur).collect();
+        assert_eq!(occurs, vec![Occur::Must, Occur::MustNot, Occur::Should]);
+        assert_eq!(query.clauses[2].tokens, vec![2, 3]);
+        assert_eq!(query.clause_of(3), 2);
+        assert_eq!(query.clauses_with(Occur::Must).collect::<Vec<_>>(), vec![0]);
+
+        // Plain words each get their own optional clause
+        let query = parse_query("god so loved", &ENGLISH);
+        assert_eq!(query.clauses.len(), 3);
+        assert!(query.clauses.iter().all(|c| c.occur == Occur::Should));
+
+        // Words may produce several tokens which keep their operator
+        let query = parse_query("-well-pleased Élie", &ENGLISH);
+        assert_eq!(tokens(&query), vec!["WELL", "PLEASED", "ELIE"]);
+        let occurs: Vec<_> = query.clauses.iter().map(|c| c.occur).collect();
+        assert_eq!(occurs, vec![Occur::MustNot, Occur::MustNot, Occur::Should]);
+
+        // Wildcards are kept, trailing question marks are not
+        let query = parse_query("*eousness bapti?e loveth? *?", &ENGLISH);
+        assert_eq!(tokens(&query), vec!["*EOUSNESS", "BAPTI?E", "LOVETH"]);
+        // Patterns are not stemmed
+        assert_eq!(query.stems, vec![None, None, Some("LOVE".to_string())]);
+
+        // Regular expressions are kept whole, with their letters folded like words
+        assert_eq!(
+            tokens(&parse_query("+/^bless(ed|ing)$/ /a?/ /naïve/", &ENGLISH)),
+            vec!["/^BLESS(ED|ING)$/", "/A?/", "/NAIVE/"]
+        );
+
+        // Lowercase "or" and a leading "OR" (with nothing to join) are words, a
+        // trailing "OR" and lone operators are ignored
+        assert_eq!(
+            tokens(&parse_query("OR faith or - + hope OR", &ENGLISH)),
+            vec!["OR", "FAITH", "OR", "HOPE"]
+        );
+    }
+
+    #[test]
+    fn test_replace_words() {
+        // Words are replaced in place, keeping the rest of the query
+        let text = r#"+Jerusalm "gold" beleive"#;
+        let query = parse_query(text, &ENGLISH);
+        assert_eq!(
+            query.replace_words(
+                text,
+                &[(0, "JERUSALEM".to_string()), (2, "BELIEVE".to_string())]
+            ),
+            r#"+Jerusalem "gold" believe"#
+        );
+        assert_eq!(query.replace_words(text, &[]), text);
+
+        // A word whose source is not in the text (the dash is dropped from the
+        // pattern) is skipped without losing later replacements
+        let text = "gr-ace* beleive";
+        let query = parse_query(text, &ENGLISH);
+        assert_eq!(
+            query.replace_words(
+                text,
+                &[(0, "GRACE".to_string()), (1, "BELIEVE".to_string())]
+            ),
+            "gr-ace* believe"
+        );
+    }
+}
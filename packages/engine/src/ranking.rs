@@ -0,0 +1,317 @@
+use crate::data::{InternalServiceRanking, VerseMatch};
+use crate::proto::service::response::verse_result::Ranking as ServiceRanking;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A rule ordering search results. Rankers are applied in order and each only
+/// breaks the ties left by the rankers before it (like MeiliSearch's ranking
+/// rules), so every ranker has a name which may be given in search options.
+pub trait Ranker: Send + Sync {
+    /// Identifies the ranker in the search config and search options
+    fn name(&self) -> &'static str;
+
+    /// Compares how well two translations matched the query, `Less` ranks `a`
+    /// first. Used to pick the top translation of each verse and to compare
+    /// verses by their top translations.
+    fn cmp_rankings(&self, _a: &ServiceRanking, _b: &ServiceRanking) -> Ordering {
+        Ordering::Equal
+    }
+
+    /// Compares two verses by what they have in common across translations,
+    /// `Less` ranks `a` first
+    fn cmp_verses(&self, _a: &VerseMatch, _b: &VerseMatch) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+/// More matched query words rank higher
+pub struct Words;
+
+impl Ranker for Words {
+    fn name(&self) -> &'static str {
+        "words"
+    }
+
+    fn cmp_rankings(&self, a: &ServiceRanking, b: &ServiceRanking) -> Ordering {
+        b.query_words.cmp(&a.query_words)
+    }
+}
+
+/// Fewer typos rank higher
+pub struct Typo;
+
+impl Ranker for Typo {
+    fn name(&self) -> &'static str {
+        "typo"
+    }
+
+    fn cmp_rankings(&self, a: &ServiceRanking, b: &ServiceRanking) -> Ordering {
+        a.typos.cmp(&b.typos)
+    }
+}
+
+/// Query words closer together and in order rank higher
+pub struct Proximity;
+
+impl Ranker for Proximity {
+    fn name(&self) -> &'static str {
+        "proximity"
+    }
+
+    fn cmp_rankings(&self, a: &ServiceRanking, b: &ServiceRanking) -> Ordering {
+        a.proximity.cmp(&b.proximity)
+    }
+}
+
+/// More exactly matched query words rank higher, then more words matched by
+/// stem and then by synonym (any of which is better than a prefix match)
+pub struct Exact;
+
+impl Ranker for Exact {
+    fn name(&self) -> &'static str {
+        "exact"
+    }
+
+    fn cmp_rankings(&self, a: &ServiceRanking, b: &ServiceRanking) -> Ordering {
+        b.exact
+            .cmp(&a.exact)
+            .then_with(|| b.stemmed.cmp(&a.stemmed))
+            .then_with(|| b.synonyms.cmp(&a.synonyms))
+    }
+}
+
+/// More popular verses rank higher
+pub struct Popularity;
+
+impl Ranker for Popularity {
+    fn name(&self) -> &'static str {
+        "popularity"
+    }
+
+    fn cmp_verses(&self, a: &VerseMatch, b: &VerseMatch) -> Ordering {
+        b.popularity.cmp(&a.popularity)
+    }
+}
+
+/// Verses with a higher BM25 relevance to the query rank higher
+pub struct Relevance;
+
+impl Ranker for Relevance {
+    fn name(&self) -> &'static str {
+        "relevance"
+    }
+
+    fn cmp_verses(&self, a: &VerseMatch, b: &VerseMatch) -> Ordering {
+        b.relevance().cmp(&a.relevance())
+    }
+}
+
+pub static WORDS: Words = Words;
+pub static TYPO: Typo = Typo;
+pub static PROXIMITY: Proximity = Proximity;
+pub static EXACT: Exact = Exact;
+pub static POPULARITY: Popularity = Popularity;
+pub static RELEVANCE: Relevance = Relevance;
+
+/// The ranking rules used unless the search config or options name others.
+/// Relevance and popularity don't compare translations, so translations are
+/// picked by words, proximity, exact and typo while verses are ordered by words,
+/// proximity, relevance and popularity before exact and typo.
+pub static DEFAULT_RANKING_RULES: &[&str] = &[
+    "words",
+    "proximity",
+    "relevance",
+    "popularity",
+    "exact",
+    "typo",
+];
+
+/// Returns the ranker with the given name
+pub fn ranker_by_name(name: &str) -> Option<&'static dyn Ranker> {
+    match name {
+        "words" => Some(&WORDS),
+        "typo" => Some(&TYPO),
+        "proximity" => Some(&PROXIMITY),
+        "exact" => Some(&EXACT),
+        "popularity" => Some(&POPULARITY),
+        "relevance" => Some(&RELEVANCE),
+        _ => None,
+    }
+}
+
+/// An ordered list of rankers, earlier rankers take precedence
+#[derive(Clone)]
+pub struct RankingRules {
+    rankers: Vec<&'static dyn Ranker>,
+}
+
+impl RankingRules {
+    /// Looks up rankers by name, skipping unknown names, or returns `None` if no
+    /// name is known
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Option<Self> {
+        let rankers: Vec<_> = names
+            .iter()
+            .filter_map(|name| ranker_by_name(name.as_ref()))
+            .collect();
+        if rankers.is_empty() {
+            None
+        } else {
+            Some(Self { rankers })
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.rankers.iter().map(|r| r.name()).collect()
+    }
+
+    /// Compares two translations of a verse, requested translations which matched
+    /// the query always rank first and remaining ties fall back to translation
+    /// index
+    pub fn cmp_translations(
+        &self,
+        a: &InternalServiceRanking,
+        b: &InternalServiceRanking,
+    ) -> Ordering {
+        let matched = |r: &InternalServiceRanking| (r.is_enabled(), r.ranking.query_words > 0);
+        matched(b)
+            .cmp(&matched(a))
+            .then_with(|| {
+                self.rankers
+                    .iter()
+                    .map(|r| r.cmp_rankings(&a.ranking, &b.ranking))
+                    .find(|o| *o != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            })
+            .then_with(|| a.idx().cmp(&b.idx()))
+    }
+
+    /// Compares two verses by their top translations and what they have in
+    /// common across translations, remaining ties fall back to canonical order so
+    /// results are stable across pages
+    pub fn cmp_verses(&self, a: &VerseMatch, b: &VerseMatch) -> Ordering {
+        let a_top = &a.top_ranking(self).ranking;
+        let b_top = &b.top_ranking(self).ranking;
+        self.rankers
+            .iter()
+            .map(|r| {
+                r.cmp_rankings(a_top, b_top)
+                    .then_with(|| r.cmp_verses(a, b))
+            })
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.key.cmp(&b.key))
+    }
+}
+
+impl Default for RankingRules {
+    fn default() -> Self {
+        Self::from_names(DEFAULT_RANKING_RULES).unwrap()
+    }
+}
+
+impl fmt::Debug for RankingRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::data::VerseKey;
+
+    fn verse(verse: u32, popularity: u64) -> VerseMatch {
+        let key = VerseKey {
+            book: 0,
+            chapter: 1,
+            verse,
+        };
+        let mut verse_match = VerseMatch::new(key, popularity, &[true, true, true]);
+        verse_match.inc_query_words(0, 0);
+        verse_match
+    }
+
+    #[test]
+    fn test_ranking_rules() {
+        let rules = RankingRules::from_names(&["typo", "klingon", "popularity"]).unwrap();
+        assert_eq!(rules.names(), vec!["typo", "popularity"]);
+        assert!(RankingRules::from_names(&["klingon"]).is_none());
+        assert_eq!(RankingRules::default().names(), DEFAULT_RANKING_RULES);
+
+        // A popular verse with a typo and a less popular verse without one
+        let mut popular = verse(1, 100);
+        popular.inc_typos(0);
+        let mut exact = verse(2, 10);
+        exact.inc_exact(0);
+        assert_eq!(rules.cmp_verses(&exact, &popular), Ordering::Less);
+        let rules = RankingRules::from_names(&["exact"]).unwrap();
+        assert_eq!(rules.cmp_verses(&exact, &popular), Ordering::Less);
+        let rules = RankingRules::from_names(&["popularity", "typo"]).unwrap();
+        assert_eq!(rules.cmp_verses(&popular, &exact), Ordering::Less);
+
+        // The top translation is picked by the same rules
+        exact.inc_query_words(1, 0);
+        exact.inc_query_words(1, 1);
+        assert_eq!(exact.top_translation(&rules), 0);
+        let rules = RankingRules::from_names(&["words"]).unwrap();
+        assert_eq!(exact.top_translation(&rules), 1);
+        let rules = RankingRules::from_names(&["typo", "exact"]).unwrap();
+        assert_eq!(exact.top_translation(&rules), 0);
+    }
+
+    #[test]
+    fn test_default_ranking_rules() {
+        // Verses are ordered by words, proximity, relevance, popularity and then
+        // exact and typo
+        let mut verses = Vec::new();
+        let mut near = verse(1, 0);
+        near.inc_query_words(0, 1);
+        near.add_positions(0, 0, &[0]);
+        near.add_positions(0, 1, &[1]);
+        near.compute_proximity();
+        near.inc_typos(0);
+        verses.push(near);
+        let mut far = verse(2, 100);
+        far.inc_query_words(0, 1);
+        far.add_positions(0, 0, &[0]);
+        far.add_positions(0, 1, &[9]);
+        far.compute_proximity();
+        far.inc_exact(0);
+        verses.push(far);
+        let mut relevant = verse(3, 0);
+        relevant.set_relevance(2.0);
+        relevant.inc_typos(0);
+        verses.push(relevant);
+        let mut popular = verse(4, 100);
+        popular.set_relevance(1.0);
+        popular.inc_typos(0);
+        verses.push(popular);
+        let mut exact = verse(6, 50);
+        exact.set_relevance(1.0);
+        exact.inc_exact(0);
+        let mut canonical = verse(5, 50);
+        canonical.set_relevance(1.0);
+        verses.push(canonical);
+        verses.push(exact);
+
+        let rules = RankingRules::default();
+        verses.reverse();
+        verses.sort_by(|a, b| rules.cmp_verses(a, b));
+        let order: Vec<_> = verses.iter().map(|v| v.key.verse).collect();
+        assert_eq!(order, vec![1, 2, 3, 4, 6, 5]);
+
+        // Translations are picked by words, proximity, exact and typo
+        let mut verse = verse(1, 0);
+        verse.inc_typos(0);
+        verse.inc_query_words(1, 0);
+        verse.inc_query_words(2, 0);
+        verse.inc_exact(2);
+        assert_eq!(verse.top_translation(&rules), 2);
+        verse.inc_exact(1);
+        assert_eq!(verse.top_translation(&rules), 1);
+        verse.inc_typos(1);
+        verse.inc_exact(0);
+        verse.inc_exact(0);
+        assert_eq!(verse.top_translation(&rules), 0);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::proto::data::{Book, VerseKey};
+use crate::proto::service::{book_range, KeyRange};
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // <book> <chapter>[<sep><verse>[-<verse>]], e.g., "John 3:16", "jn 3 16", "1 Cor 13:4-7", "Ps 23"
+    static ref REFERENCE_RE: Regex = Regex::new(
+        r"^\s*([1-3]?\s*[A-Z][A-Z .]*?)\.?\s*(\d{1,3})(?:\s*[:. ]\s*(\d{1,3})(?:\s*[-–—]\s*(\d{1,3}))?)?\s*$"
+    )
+    .expect("Could not compile regex for parsing references");
+}
+
+/// The largest chapter or verse number, `VerseKey::to_be_bytes` stores each in
+/// one byte
+pub(crate) static MAX_NUMBER: u32 = 255;
+
+/// Leading ordinals accepted for numbered books, longest forms first so that
+/// "III" is not read as "I" followed by "II"
+static ORDINALS: &[(&str, &str)] = &[
+    ("FIRST", "FIRST"),
+    ("SECOND", "SECOND"),
+    ("THIRD", "THIRD"),
+    ("1ST", "FIRST"),
+    ("2ND", "SECOND"),
+    ("3RD", "THIRD"),
+    ("III", "THIRD"),
+    ("II", "SECOND"),
+    ("I", "FIRST"),
+    ("1", "FIRST"),
+    ("2", "SECOND"),
+    ("3", "THIRD"),
+];
+
+/// Book names as understood by `Book::from_string`, without ordinals, used
+/// for prefix matching of abbreviations such as "GEN" or "ROM"
+static NAMES: &[&str] = &[
+    "GENESIS",
+    "EXODUS",
+    "LEVITICUS",
+    "NUMBERS",
+    "DEUTERONOMY",
+    "JOSHUA",
+    "JUDGES",
+    "RUTH",
+    "EZRA",
+    "NEHEMIAH",
+    "ESTHER",
+    "JOB",
+    "PSALMS",
+    "PROVERBS",
+    "ECCLESIASTES",
+    "SONG OF SOLOMON",
+    "ISAIAH",
+    "JEREMIAH",
+    "LAMENTATIONS",
+    "EZEKIEL",
+    "DANIEL",
+    "HOSEA",
+    "JOEL",
+    "AMOS",
+    "OBADIAH",
+    "JONAH",
+    "MICAH",
+    "NAHUM",
+    "HABAKKUK",
+    "ZEPHANIAH",
+    "HAGGAI",
+    "ZECHARIAH",
+    "MALACHI",
+    "MATTHEW",
+    "MARK",
+    "LUKE",
+    "JOHN",
+    "ACTS",
+    "ROMANS",
+    "GALATIANS",
+    "EPHESIANS",
+    "PHILIPPIANS",
+    "COLOSSIANS",
+    "TITUS",
+    "PHILEMON",
+    "HEBREWS",
+    "JAMES",
+    "JUDE",
+    "REVELATION",
+];
+
+/// Names of books which are only valid with an ordinal
+static NUMBERED_NAMES: &[&str] = &[
+    "SAMUEL",
+    "KINGS",
+    "CHRONICLES",
+    "CORINTHIANS",
+    "THESSALONIANS",
+    "TIMOTHY",
+    "PETER",
+    "JOHN",
+];
+
+/// Common abbreviations which are not simple prefixes of a book name (or
+/// which are ambiguous prefixes with a conventional meaning)
+static ABBREVIATIONS: &[(&str, &str)] = &[
+    ("GN", "GENESIS"),
+    ("EX", "EXODUS"),
+    ("LV", "LEVITICUS"),
+    ("NB", "NUMBERS"),
+    ("NM", "NUMBERS"),
+    ("DT", "DEUTERONOMY"),
+    ("JSH", "JOSHUA"),
+    ("JG", "JUDGES"),
+    ("JDG", "JUDGES"),
+    ("JDGS", "JUDGES"),
+    ("RTH", "RUTH"),
+    ("SM", "SAMUEL"),
+    ("SA", "SAMUEL"),
+    ("KGS", "KINGS"),
+    ("KI", "KINGS"),
+    ("CHR", "CHRONICLES"),
+    ("CH", "CHRONICLES"),
+    ("NEH", "NEHEMIAH"),
+    ("EST", "ESTHER"),
+    ("JB", "JOB"),
+    ("PS", "PSALMS"),
+    ("PSS", "PSALMS"),
+    ("PSLM", "PSALMS"),
+    ("PRV", "PROVERBS"),
+    ("QOH", "ECCLESIASTES"),
+    ("SONG", "SONG OF SOLOMON"),
+    ("SONG OF SONGS", "SONG OF SOLOMON"),
+    ("SOS", "SONG OF SOLOMON"),
+    ("CANTICLES", "SONG OF SOLOMON"),
+    ("JER", "JEREMIAH"),
+    ("EZK", "EZEKIEL"),
+    ("DN", "DANIEL"),
+    ("JL", "JOEL"),
+    ("OB", "OBADIAH"),
+    ("JNH", "JONAH"),
+    ("MC", "MICAH"),
+    ("NA", "NAHUM"),
+    ("HB", "HABAKKUK"),
+    ("ZP", "ZEPHANIAH"),
+    ("HG", "HAGGAI"),
+    ("ZC", "ZECHARIAH"),
+    ("ML", "MALACHI"),
+    ("MT", "MATTHEW"),
+    ("MK", "MARK"),
+    ("MRK", "MARK"),
+    ("LK", "LUKE"),
+    ("JN", "JOHN"),
+    ("JHN", "JOHN"),
+    ("RM", "ROMANS"),
+    ("CO", "CORINTHIANS"),
+    ("COR", "CORINTHIANS"),
+    ("THESS", "THESSALONIANS"),
+    ("TH", "THESSALONIANS"),
+    ("TM", "TIMOTHY"),
+    ("PHIL", "PHILIPPIANS"),
+    ("PHP", "PHILIPPIANS"),
+    ("PHM", "PHILEMON"),
+    ("PHLM", "PHILEMON"),
+    ("JAS", "JAMES"),
+    ("JM", "JAMES"),
+    ("PT", "PETER"),
+    ("PET", "PETER"),
+    ("JUD", "JUDE"),
+    ("REV", "REVELATION"),
+    ("REVELATIONS", "REVELATION"),
+    ("RV", "REVELATION"),
+];
+
+/// Books consisting of a single chapter, where "Jude 5" means verse 5
+static SINGLE_CHAPTER_BOOKS: &[Book] = &[
+    Book::Obadiah,
+    Book::Philemon,
+    Book::SecondJohn,
+    Book::ThirdJohn,
+    Book::Jude,
+];
+
+/// A parsed scripture reference such as "John 3:16-18" or "Psalms 23"
+#[cfg_attr(test, derive(Debug))]
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct Reference {
+    pub book: Book,
+    pub chapter: u32,
+    /// Inclusive verse range, `None` when the whole chapter was requested
+    pub verses: Option<(u32, u32)>,
+}
+
+impl Reference {
+    /// Returns inclusive start and end keys covering this reference
+    pub fn key_range(&self) -> (VerseKey, VerseKey) {
+        let (start, end) = self.verses.unwrap_or((0, u8::MAX as u32));
+        (
+            VerseKey {
+                book: self.book as i32,
+                chapter: self.chapter,
+                verse: start,
+            },
+            VerseKey {
+                book: self.book as i32,
+                chapter: self.chapter,
+                verse: end,
+            },
+        )
+    }
+}
+
+/// Looks up a book by an unnumbered name or abbreviation
+fn lookup_name(name: &str) -> Option<&'static str> {
+    if let Some(name) = NAMES.iter().find(|n| **n == name) {
+        return Some(name);
+    }
+    if let Some((_, name)) = ABBREVIATIONS.iter().find(|(abbr, _)| *abbr == name) {
+        return Some(name);
+    }
+    // Fall back to an unambiguous prefix of a full name, e.g., "GEN" or "ROM"
+    if name.len() < 3 {
+        return None;
+    }
+    let mut matches = NAMES
+        .iter()
+        .chain(NUMBERED_NAMES.iter())
+        .filter(|n| n.starts_with(name))
+        .unique();
+    match (matches.next(), matches.next()) {
+        (Some(n), None) => Some(n),
+        _ => None,
+    }
+}
+
+/// Resolves a book from user input, e.g., "1 Cor", "I Corinthians", "First Corinthians"
+pub fn parse_book(input: &str) -> Option<Book> {
+    let name = input
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase();
+
+    // Try to read a leading ordinal for numbered books (1 John, II Kings, First Peter)
+    for (ordinal, word) in ORDINALS {
+        if let Some(rest) = name.strip_prefix(ordinal) {
+            // Only digits may be attached directly to the name ("1Cor"), otherwise
+            // "Isa" would read as "I Sa"
+            let is_digit = ordinal.chars().all(|c| c.is_ascii_digit());
+            if !is_digit && !rest.starts_with(' ') {
+                continue;
+            }
+            let rest = rest.trim_start();
+            if let Some(base) = lookup_name(rest) {
+                if let Ok(book) = Book::from_string(&format!("{} {}", word, base)) {
+                    return Some(book);
+                }
+            }
+        }
+    }
+
+    // No ordinal, the whole thing must be a name
+    lookup_name(&name).and_then(|base| Book::from_string(base).ok())
+}
+
+/// Attempts to parse the input as a scripture reference
+pub fn parse_reference(input: &str) -> Option<Reference> {
+    let input = input.to_uppercase();
+    let caps = REFERENCE_RE.captures(&input)?;
+    let book = parse_book(caps.get(1)?.as_str())?;
+    let chapter = caps.get(2)?.as_str().parse::<u32>().ok()?;
+    let verse = caps.get(3).and_then(|m| m.as_str().parse::<u32>().ok());
+    let end = caps.get(4).and_then(|m| m.as_str().parse::<u32>().ok());
+    // Larger numbers would wrap around to another verse, search for them as text
+    if [Some(chapter), verse, end]
+        .iter()
+        .flatten()
+        .any(|n| *n > MAX_NUMBER)
+    {
+        return None;
+    }
+
+    let reference = match (verse, end) {
+        (Some(start), Some(end)) if end >= start => Reference {
+            book,
+            chapter,
+            verses: Some((start, end)),
+        },
+        (Some(_), Some(_)) => return None,
+        (Some(verse), None) => Reference {
+            book,
+            chapter,
+            verses: Some((verse, verse)),
+        },
+        // "Jude 5" refers to a verse since there is only one chapter
+        (None, _) if SINGLE_CHAPTER_BOOKS.contains(&book) && chapter > 1 => Reference {
+            book,
+            chapter: 1,
+            verses: Some((chapter, chapter)),
+        },
+        (None, _) => Reference {
+            book,
+            chapter,
+            verses: None,
+        },
+    };
+
+    Some(reference)
+}
+
+/// Parses one side of a range such as "Romans", "John 3", "John 3:16" or, when a
+/// book is given from the other side of the range, "5" or "4:2"
+fn parse_location(input: &str, default_book: Option<Book>) -> Option<KeyRange> {
+    lazy_static! {
+        static ref LOCATION_RE: Regex = Regex::new(
+            r"^\s*([1-3]?\s*[A-Z][A-Z .]*?)?\s*(?:(\d{1,3})(?:\s*[:.]\s*(\d{1,3}))?)?\s*$"
+        )
+        .expect("Could not compile regex for parsing locations");
+    }
+    let input = input.to_uppercase();
+    let caps = LOCATION_RE.captures(&input)?;
+    let book = match caps.get(1) {
+        Some(name) => parse_book(name.as_str())?,
+        None => default_book?,
+    };
+    let chapter = caps.get(2).and_then(|m| m.as_str().parse::<u32>().ok());
+    let verse = caps.get(3).and_then(|m| m.as_str().parse::<u32>().ok());
+    if [chapter, verse].iter().flatten().any(|n| *n > MAX_NUMBER) {
+        return None;
+    }
+    let (mut start, mut end) = book_range(book, book);
+    if let Some(chapter) = chapter {
+        start.chapter = chapter;
+        end.chapter = chapter;
+    }
+    if let Some(verse) = verse {
+        start.verse = verse;
+        end.verse = verse;
+    }
+    Some((start, end))
+}
+
+/// Parses a range of verses such as "Romans-Jude", "John 3-5" or "John 3:16-4:2"
+pub fn parse_range(input: &str) -> Option<KeyRange> {
+    if let Some(reference) = parse_reference(input) {
+        return Some(reference.key_range());
+    }
+    let mut sides = input.splitn(2, &['-', '–', '—'][..]);
+    let (start, end) = match (sides.next(), sides.next()) {
+        (Some(first), Some(last)) => {
+            let (start, _) = parse_location(first, None)?;
+            let (_, end) = parse_location(last, Some(Book::from_i32(start.book)?))?;
+            (start, end)
+        }
+        (Some(only), None) => parse_location(only, None)?,
+        _ => return None,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_book() {
+        assert_eq!(parse_book("John"), Some(Book::John));
+        assert_eq!(parse_book("jn"), Some(Book::John));
+        assert_eq!(parse_book("1 John"), Some(Book::FirstJohn));
+        assert_eq!(parse_book("I John"), Some(Book::FirstJohn));
+        assert_eq!(parse_book("iii jn"), Some(Book::ThirdJohn));
+        assert_eq!(
+            parse_book("First Corinthians"),
+            Some(Book::FirstCorinthians)
+        );
+        assert_eq!(parse_book("2nd Kgs"), Some(Book::SecondKings));
+        assert_eq!(parse_book("1 Cor."), Some(Book::FirstCorinthians));
+        assert_eq!(parse_book("Isa"), Some(Book::Isaiah));
+        assert_eq!(parse_book("1Jn"), Some(Book::FirstJohn));
+        assert_eq!(parse_book("Joh"), Some(Book::John));
+        assert_eq!(parse_book("Song of Songs"), Some(Book::SongOfSolomon));
+        assert_eq!(parse_book("Ps"), Some(Book::Psalms));
+        assert_eq!(parse_book("Psalm"), Some(Book::Psalms));
+        assert_eq!(parse_book("Phil"), Some(Book::Philippians));
+        assert_eq!(parse_book("Jo"), None);
+        assert_eq!(parse_book("Corinthians"), None);
+        assert_eq!(parse_book("love"), None);
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        assert_eq!(
+            parse_reference("John 3:16"),
+            Some(Reference {
+                book: Book::John,
+                chapter: 3,
+                verses: Some((16, 16)),
+            })
+        );
+        assert_eq!(parse_reference("jn 3 16"), parse_reference("John 3:16"));
+        assert_eq!(
+            parse_reference("1 Cor 13:4-7"),
+            Some(Reference {
+                book: Book::FirstCorinthians,
+                chapter: 13,
+                verses: Some((4, 7)),
+            })
+        );
+        assert_eq!(
+            parse_reference("Ps 23"),
+            Some(Reference {
+                book: Book::Psalms,
+                chapter: 23,
+                verses: None,
+            })
+        );
+        assert_eq!(
+            parse_reference("Jude 5"),
+            Some(Reference {
+                book: Book::Jude,
+                chapter: 1,
+                verses: Some((5, 5)),
+            })
+        );
+        assert_eq!(parse_reference("John"), None);
+        assert_eq!(parse_reference("John 3:16-2"), None);
+        assert_eq!(parse_reference("god so loved"), None);
+        assert_eq!(parse_reference("seventy 7"), None);
+        assert_eq!(parse_reference("John 259:16"), None);
+        assert_eq!(parse_reference("John 3:272"), None);
+        assert_eq!(parse_reference("John 3:16-999"), None);
+        assert_eq!(parse_reference("Ps 279"), None);
+        assert_eq!(parse_reference("Jude 256"), None);
+        assert!(parse_reference("Ps 119:176").is_some());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("Romans-Jude"),
+            Some(book_range(Book::Romans, Book::Jude))
+        );
+        let (start, end) = parse_range("John 3-5").unwrap();
+        assert_eq!((start.book, start.chapter, start.verse), (42, 3, 0));
+        assert_eq!((end.book, end.chapter, end.verse), (42, 5, 255));
+        let (start, end) = parse_range("John 3:16-4:2").unwrap();
+        assert_eq!((start.chapter, start.verse), (3, 16));
+        assert_eq!((end.chapter, end.verse), (4, 2));
+        let (start, end) = parse_range("John 3:16-18").unwrap();
+        assert_eq!((start.chapter, start.verse), (3, 16));
+        assert_eq!((end.chapter, end.verse), (3, 18));
+        let (start, end) = parse_range("John 3:16—4:2").unwrap();
+        assert_eq!((start.chapter, start.verse), (3, 16));
+        assert_eq!((end.chapter, end.verse), (4, 2));
+        assert_eq!(parse_range("John 3-259"), None);
+        assert_eq!(parse_range("John 3:16-4:300"), None);
+        assert_eq!(parse_range("Jude-Romans"), None);
+        assert_eq!(parse_range("love"), None);
+    }
+}
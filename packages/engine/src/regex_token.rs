@@ -0,0 +1,181 @@
+use regex_automata::dense::{Builder, DenseDFA};
+use regex_automata::StateID;
+
+/// The longest pattern which will be compiled, long patterns can produce huge
+/// automata
+static MAX_PATTERN_LEN: usize = 64;
+/// The deepest nesting of groups and repetitions allowed in a pattern
+static MAX_NEST: u32 = 8;
+/// The most states an automaton may have, short patterns can still produce
+/// exponentially many states (e.g., "[ab]*a[ab]{20}")
+static MAX_STATES: usize = 10_000;
+
+/// A DFA state identifier which only counts up to `MAX_STATES`, so building the
+/// automaton of a pattern which needs more states stops with an error as soon
+/// as it runs out of identifiers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegexStateID(u16);
+
+/// Identifiers never exceed `MAX_STATES`, which fits in a `u16`
+unsafe impl StateID for RegexStateID {
+    fn from_usize(n: usize) -> Self {
+        RegexStateID(n as u16)
+    }
+
+    fn to_usize(self) -> usize {
+        self.0 as usize
+    }
+
+    fn max_id() -> usize {
+        MAX_STATES
+    }
+
+    fn read_bytes(slice: &[u8]) -> Self {
+        RegexStateID(u16::read_bytes(slice))
+    }
+
+    fn write_bytes(self, slice: &mut [u8]) {
+        self.0.write_bytes(slice)
+    }
+}
+
+/// Returns the pattern of a regex token (e.g., "^bless(ed|ing)$" for
+/// "/^bless(ed|ing)$/")
+pub fn regex_pattern(token: &str) -> Option<&str> {
+    token
+        .strip_prefix('/')
+        .and_then(|rest| rest.strip_suffix('/'))
+        .filter(|pattern| !pattern.is_empty())
+}
+
+/// Folds the letters of a pattern the way indexed words were folded (e.g.,
+/// "naïve" to "NAIVE"), leaving escapes (e.g., `\w` or `\p{Greek}`), flags and
+/// group names as they are. Letters which fold to several characters (e.g.,
+/// ligatures) are kept so quantifiers still apply to a single character.
+pub fn fold_pattern(pattern: &str, fold: impl Fn(&str) -> String) -> String {
+    let mut folded = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                folded.push(c);
+                if let Some(escaped) = chars.next() {
+                    folded.push(escaped);
+                    if chars.peek() == Some(&'{') {
+                        for c in &mut chars {
+                            folded.push(c);
+                            if c == '}' {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            '(' if chars.peek() == Some(&'?') => {
+                folded.push(c);
+                for c in &mut chars {
+                    folded.push(c);
+                    if c == ')' || c == ':' || c == '>' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_ascii_punctuation() || c.is_whitespace() => folded.push(c),
+            c => {
+                let letter = fold(&c.to_string());
+                let mut letters = letter.chars();
+                match (letters.next(), letters.next()) {
+                    (Some(letter), None) => folded.push(letter),
+                    // Diacritics on their own fold to nothing
+                    (None, _) => {}
+                    _ => folded.push(c),
+                }
+            }
+        }
+    }
+    folded
+}
+
+/// Compiles a pattern into an automaton matching whole index tokens, returns
+/// `None` if the pattern is invalid or too complex. Patterns are always matched
+/// against entire tokens so `^` and `$` anchors are optional.
+pub fn build_regex(pattern: &str) -> Option<DenseDFA<Vec<RegexStateID>, RegexStateID>> {
+    let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+    let pattern = match pattern.strip_suffix('$') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => pattern,
+    };
+    if pattern.len() > MAX_PATTERN_LEN {
+        return None;
+    }
+    Builder::new()
+        .anchored(true)
+        .case_insensitive(true)
+        // Tokens may contain any letter, `.` must match a whole character
+        .unicode(true)
+        .nest_limit(MAX_NEST)
+        // Premultiplied identifiers would count transitions rather than states
+        .premultiply(false)
+        .build_with_size::<RegexStateID>(pattern)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use fst::{IntoStreamer, Set};
+
+    fn search(pattern: &str) -> Vec<String> {
+        let set = Set::from_iter(vec![
+            "BLESS",
+            "BLESSED",
+            "BLESSEDNESS",
+            "BLESSING",
+            "UNBLESSED",
+            "ÉLISÉE",
+        ])
+        .unwrap();
+        set.search(build_regex(pattern).unwrap())
+            .into_stream()
+            .into_strs()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_regex_pattern() {
+        assert_eq!(regex_pattern("/^bless(ed|ing)$/"), Some("^bless(ed|ing)$"));
+        assert_eq!(regex_pattern("//"), None);
+        assert_eq!(regex_pattern("/bless"), None);
+        assert_eq!(regex_pattern("bless"), None);
+    }
+
+    #[test]
+    fn test_fold_pattern() {
+        let fold = |pattern| fold_pattern(pattern, util::fold);
+        assert_eq!(fold("^bless(ed|ing)$"), "^BLESS(ED|ING)$");
+        assert_eq!(fold("naïve"), "NAIVE");
+        assert_eq!(fold("nai\u{308}ve"), "NAIVE");
+        assert_eq!(fold("é.is.e"), "E.IS.E");
+        assert_eq!(fold("\\w+\\p{Greek}"), "\\w+\\p{Greek}");
+        assert_eq!(fold("(?i)(?P<word>a)(?:b)"), "(?i)(?P<word>A)(?:B)");
+        assert_eq!(fold("ﬁ+"), "ﬁ+");
+    }
+
+    #[test]
+    fn test_build_regex() {
+        assert_eq!(search("^bless(ed|ing)$"), vec!["BLESSED", "BLESSING"]);
+        assert_eq!(search("bless(ed|ing)"), vec!["BLESSED", "BLESSING"]);
+        assert_eq!(search(".*blessed"), vec!["BLESSED", "UNBLESSED"]);
+        assert_eq!(search("bless\\w{3}"), vec!["BLESSING"]);
+        assert!(build_regex("bless(").is_none());
+        assert!(build_regex(&"a".repeat(MAX_PATTERN_LEN + 1)).is_none());
+        // Letters outside ASCII are single characters
+        assert_eq!(search("é.is.e"), vec!["ÉLISÉE"]);
+        assert_eq!(search(".{6}"), vec!["ÉLISÉE"]);
+        // Short patterns needing too many states are rejected
+        assert!(build_regex("[ab]*a[ab]{8}").is_some());
+        assert!(build_regex("[ab]*a[ab]{14}").is_none());
+        assert!(build_regex("[ab]*a[ab]{17}").is_none());
+    }
+}
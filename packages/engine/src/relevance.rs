@@ -0,0 +1,41 @@
+/// How quickly repeated occurrences of a token stop adding to its score
+static K1: f64 = 1.2;
+/// How much verse length is normalized, from none (0) to fully (1)
+static B: f64 = 0.75;
+
+/// Returns the inverse document frequency of a token found in `matches` of
+/// `total` verses, rare tokens weigh more than common ones
+pub fn idf(matches: usize, total: usize) -> f64 {
+    let matches = matches as f64;
+    (1.0 + (total as f64 - matches + 0.5) / (matches + 0.5)).ln()
+}
+
+/// Returns the BM25 score of a token with the given inverse document frequency
+/// appearing `count` times in a verse of `length` tokens
+pub fn bm25(idf: f64, count: u64, length: f64, average_length: f64) -> f64 {
+    let count = count as f64;
+    let norm = if average_length > 0.0 {
+        1.0 - B + B * length / average_length
+    } else {
+        1.0
+    };
+    idf * count * (K1 + 1.0) / (count + K1 * norm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bm25() {
+        // Rare tokens score higher than common tokens
+        assert!(idf(5, 30000) > idf(3000, 30000));
+        let idf = idf(100, 30000);
+        // Repeated tokens score higher, but less than proportionally
+        let once = bm25(idf, 1, 20.0, 20.0);
+        let twice = bm25(idf, 2, 20.0, 20.0);
+        assert!(twice > once && twice < once * 2.0);
+        // Tokens in short verses score higher than in long verses
+        assert!(bm25(idf, 1, 10.0, 20.0) > bm25(idf, 1, 40.0, 20.0));
+    }
+}
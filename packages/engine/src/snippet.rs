@@ -0,0 +1,98 @@
+use std::ops::Range;
+
+static ELLIPSIS: &str = "…";
+
+/// Trims text to around `length` characters centered on the matched words (byte
+/// ranges into the text), breaking at whitespace and marking trimmed ends with
+/// ellipses. Returns the snippet and the matched ranges which fall within it,
+/// shifted to be relative to the snippet.
+pub fn snippet(text: &str, matches: &[Range<usize>], length: usize) -> (String, Vec<Range<usize>>) {
+    let boundaries: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let char_count = boundaries.len() - 1;
+    if char_count <= length {
+        return (text.to_string(), matches.to_vec());
+    }
+    let char_at = |byte: usize| boundaries.binary_search(&byte).unwrap_or_else(|i| i);
+
+    // Focus on all matched words if they fit, otherwise on the first one
+    let focus = match (matches.first(), matches.last()) {
+        (Some(first), Some(last)) if char_at(last.end) - char_at(first.start) <= length => {
+            first.start..last.end
+        }
+        (Some(first), _) => first.clone(),
+        _ => 0..0,
+    };
+    let center = (char_at(focus.start) + char_at(focus.end)) / 2;
+    let first_char = center.saturating_sub(length / 2).min(char_count - length);
+    let mut start = boundaries[first_char];
+    let mut end = boundaries[first_char + length];
+
+    // Avoid cutting words in half where possible
+    let cuts_word = |i: usize| {
+        !text[..i].ends_with(char::is_whitespace) && !text[i..].starts_with(char::is_whitespace)
+    };
+    if start > 0 && cuts_word(start) {
+        if let Some(space) = text[start..focus.start.max(start)].find(char::is_whitespace) {
+            start += space;
+        }
+    }
+    if end < text.len() && cuts_word(end) {
+        if let Some(space) = text[focus.end.min(end)..end].rfind(char::is_whitespace) {
+            end = focus.end.min(end) + space;
+        }
+    }
+    let trimmed = text[start..end].trim();
+    let trimmed_start = start + (text[start..end].len() - text[start..end].trim_start().len());
+    let trimmed_end = trimmed_start + trimmed.len();
+
+    let prefix = if trimmed_start > 0 { ELLIPSIS } else { "" };
+    let suffix = if trimmed_end < text.len() {
+        ELLIPSIS
+    } else {
+        ""
+    };
+    let shifted = matches
+        .iter()
+        .filter(|m| m.start >= trimmed_start && m.end <= trimmed_end)
+        .map(|m| (m.start - trimmed_start + prefix.len())..(m.end - trimmed_start + prefix.len()))
+        .collect();
+
+    (format!("{}{}{}", prefix, trimmed, suffix), shifted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str, word: &str) -> Range<usize> {
+        let start = text.find(word).unwrap();
+        start..(start + word.len())
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = "For God so loved the world, that he gave his only begotten Son";
+
+        // Short text is returned as is
+        assert_eq!(snippet(text, &[], 100), (text.to_string(), vec![]));
+
+        // Long text is trimmed at word boundaries around the matches
+        let matches = vec![find(text, "world"), find(text, "gave")];
+        let (trimmed, spans) = snippet(text, &matches, 30);
+        assert_eq!(trimmed, "…the world, that he gave his…");
+        let words: Vec<_> = spans.into_iter().map(|r| &trimmed[r]).collect();
+        assert_eq!(words, vec!["world", "gave"]);
+
+        // Without matches the snippet starts at the beginning
+        assert_eq!(snippet(text, &[], 20).0, "For God so loved the…");
+
+        // Matches near the end keep the end of the text
+        let (trimmed, spans) = snippet(text, &[find(text, "Son")], 20);
+        assert_eq!(trimmed, "…only begotten Son");
+        assert_eq!(&trimmed[spans[0].clone()], "Son");
+    }
+}
@@ -0,0 +1,112 @@
+use lazy_static::lazy_static;
+use rust_stemmers::{Algorithm, Stemmer};
+
+lazy_static! {
+    static ref SNOWBALL: Stemmer = Stemmer::create(Algorithm::English);
+}
+
+/// Separates a stem from the token it was derived from in stem FST keys, tokens
+/// never contain it
+static STEM_SEPARATOR: char = '\0';
+
+/// Archaic forms which do not follow the suffix rules and their modern forms
+static IRREGULAR: &[(&str, &str)] = &[
+    ("SAITH", "SAY"),
+    ("HATH", "HAVE"),
+    ("HAST", "HAVE"),
+    ("DOTH", "DO"),
+    ("DOST", "DO"),
+    ("DIDST", "DID"),
+    ("WAST", "WAS"),
+    ("WERT", "WERE"),
+    ("SHALT", "SHALL"),
+    ("WILT", "WILL"),
+    ("CANST", "CAN"),
+    ("SPAKE", "SPEAK"),
+];
+
+/// Archaic verb endings (e.g., "loveth", "lovest", "lovedst"), modern endings
+/// which stem the same way and the shortest part of the word which may be left
+/// before the ending (e.g., "DO" from "DOETH"), longest first
+static ARCHAIC_SUFFIXES: &[(&str, &str, usize)] = &[
+    ("EDST", "ED", 2),
+    ("EETH", "EE", 1),
+    ("EEST", "EE", 1),
+    ("ETH", "ING", 2),
+    ("EST", "ING", 2),
+];
+
+/// Words which end like archaic verbs but are not verbs
+static NOT_ARCHAIC: &[&str] = &[
+    "BEHEST",
+    "CONQUEST",
+    "EARNEST",
+    "ELISABETH",
+    "FOREST",
+    "HARVEST",
+    "HONEST",
+    "INTEREST",
+    "JAPHETH",
+    "MANIFEST",
+    "MODEST",
+    "NAZARETH",
+    "PRIEST",
+    "REQUEST",
+    "TEMPEST",
+];
+
+/// Rewrites archaic verb forms into modern forms (e.g., "LOVETH" to "LOVING")
+fn modernize(token: &str) -> String {
+    if let Some((_, modern)) = IRREGULAR.iter().find(|(archaic, _)| *archaic == token) {
+        return modern.to_string();
+    }
+    if NOT_ARCHAIC.contains(&token) {
+        return token.to_string();
+    }
+    ARCHAIC_SUFFIXES
+        .iter()
+        .find_map(|(suffix, modern, min_len)| {
+            token
+                .strip_suffix(suffix)
+                .filter(|stem| stem.len() >= *min_len)
+                .map(|stem| format!("{}{}", stem, modern))
+        })
+        .unwrap_or_else(|| token.to_string())
+}
+
+/// Stems an English token (e.g., "LOVED", "LOVETH" and "LOVEDST" all stem to
+/// "LOVE") with the Snowball (Porter 2) stemmer after rewriting archaic forms
+pub fn stem(token: &str) -> String {
+    let modern = modernize(token).to_lowercase();
+    SNOWBALL.stem(&modern).to_uppercase()
+}
+
+/// Returns the stem FST key mapping a stem to a token
+pub fn stem_key(stem: &str, token: &str) -> String {
+    format!("{}{}{}", stem, STEM_SEPARATOR, token)
+}
+
+/// Returns the prefix of the stem FST keys of every token with the given stem
+pub fn stem_prefix(stem: &str) -> String {
+    format!("{}{}", stem, STEM_SEPARATOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stem() {
+        for word in &[
+            "LOVE", "LOVED", "LOVES", "LOVING", "LOVETH", "LOVEST", "LOVEDST",
+        ] {
+            assert_eq!(stem(word), "LOVE", "{}", word);
+        }
+        assert_eq!(stem("SAITH"), stem("SAYS"));
+        assert_eq!(stem("SEETH"), stem("SEE"));
+        assert_eq!(stem("DOETH"), "DO");
+        assert_eq!(stem("BELIEVETH"), stem("BELIEVE"));
+        assert_eq!(stem("HONEST"), "HONEST");
+        assert_eq!(stem("BEST"), "BEST");
+    }
+}
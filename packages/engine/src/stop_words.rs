@@ -0,0 +1,74 @@
+use crate::proto::engine::StopWordMode;
+use crate::util::fold;
+use std::collections::BTreeSet;
+
+/// Words too common to be useful in searches and how they are handled. The same
+/// list is used to build the index and to search it so it is stored with the
+/// index.
+pub struct StopWords {
+    words: BTreeSet<String>,
+    mode: StopWordMode,
+}
+
+impl StopWords {
+    pub fn new(words: BTreeSet<String>, mode: StopWordMode) -> Self {
+        Self { words, mode }
+    }
+
+    /// Parses a stop word list with one word per line, blank lines and lines
+    /// starting with `#` are ignored
+    pub fn parse_list(text: &str) -> BTreeSet<String> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .map(fold)
+            .filter(|word| !word.is_empty())
+            .collect()
+    }
+
+    pub fn words(&self) -> &BTreeSet<String> {
+        &self.words
+    }
+
+    pub fn mode(&self) -> StopWordMode {
+        self.mode
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.words.contains(token)
+    }
+
+    /// Returns true if the given token is not stored in the index
+    pub fn is_dropped(&self, token: &str) -> bool {
+        self.mode == StopWordMode::Drop && self.contains(token)
+    }
+
+    /// Returns true if the given token is stored in the index but should only
+    /// match as part of a phrase
+    pub fn is_phrase_only(&self, token: &str) -> bool {
+        self.mode == StopWordMode::PhraseOnly && self.contains(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_words() {
+        let words = StopWords::parse_list("# English\nthe\n\n  And \nÉlie\n");
+        assert_eq!(
+            words.iter().map(String::as_str).collect::<Vec<_>>(),
+            vec!["AND", "ELIE", "THE"]
+        );
+
+        let dropped = StopWords::new(words.clone(), StopWordMode::Drop);
+        assert!(dropped.is_dropped("THE"));
+        assert!(!dropped.is_phrase_only("THE"));
+        assert!(!dropped.is_dropped("GOD"));
+
+        let phrase_only = StopWords::new(words, StopWordMode::PhraseOnly);
+        assert!(!phrase_only.is_dropped("THE"));
+        assert!(phrase_only.is_phrase_only("THE"));
+    }
+}
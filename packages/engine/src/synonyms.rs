@@ -0,0 +1,71 @@
+use crate::util::fold;
+use std::collections::{BTreeSet, HashMap};
+
+/// Groups of words which match each other (e.g., "you", "thee", "thou" and
+/// "ye"), expanded at query time
+pub struct Synonyms {
+    /// Token => Every other token in the groups containing it
+    map: HashMap<String, BTreeSet<String>>,
+}
+
+impl Synonyms {
+    pub fn new(groups: &[Vec<String>]) -> Self {
+        let mut map: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for group in groups {
+            for word in group {
+                map.entry(word.clone())
+                    .or_default()
+                    .extend(group.iter().filter(|w| *w != word).cloned());
+            }
+        }
+        Self { map }
+    }
+
+    /// Parses a synonym list with one group of comma separated words per line,
+    /// blank lines and lines starting with `#` are ignored
+    pub fn parse_list(text: &str) -> Vec<Vec<String>> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                line.split(',')
+                    .map(fold)
+                    .filter(|word| !word.is_empty())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|group| group.len() > 1)
+            .collect()
+    }
+
+    /// Returns the synonyms of the given token
+    pub fn get<'a>(&'a self, token: &str) -> impl Iterator<Item = &'a str> {
+        self.map
+            .get(token)
+            .into_iter()
+            .flat_map(|synonyms| synonyms.iter().map(String::as_str))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synonyms() {
+        let groups = Synonyms::parse_list("# Pronouns\nyou, thee, thou\n\nghost,spirit\nlove\n");
+        assert_eq!(
+            groups,
+            vec![vec!["THEE", "THOU", "YOU"], vec!["GHOST", "SPIRIT"]]
+        );
+
+        let synonyms = Synonyms::new(&groups);
+        assert_eq!(
+            synonyms.get("YOU").collect::<Vec<_>>(),
+            vec!["THEE", "THOU"]
+        );
+        assert_eq!(synonyms.get("SPIRIT").collect::<Vec<_>>(), vec!["GHOST"]);
+        assert_eq!(synonyms.get("LOVE").count(), 0);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::proto::service::response::Timings;
+
+/// Returns the current time in milliseconds relative to an arbitrary origin, in
+/// the same format as `performance.now()` in JavaScript
+pub type Clock = fn() -> f64;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn instant_now() -> f64 {
+    use lazy_static::lazy_static;
+    use std::time::Instant;
+
+    lazy_static! {
+        static ref ORIGIN: Instant = Instant::now();
+    }
+
+    ORIGIN.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Returns the clock to use when none has been set
+#[cfg(not(target_arch = "wasm32"))]
+pub fn default_clock() -> Option<Clock> {
+    Some(instant_now)
+}
+
+/// `Instant::now()` panics on wasm32-unknown-unknown (and wasm-timer's does not
+/// work in web workers https://github.com/tomaka/wasm-timer/issues/12) so wasm
+/// builds have no clock unless one is provided with `VersearchIndex::set_clock`
+#[cfg(target_arch = "wasm32")]
+pub fn default_clock() -> Option<Clock> {
+    None
+}
+
+/// Measures the time taken by each phase of a search
+pub struct PhaseTimer {
+    clock: Option<Clock>,
+    last: f64,
+    timings: Timings,
+}
+
+impl PhaseTimer {
+    pub fn start(clock: Option<Clock>) -> Self {
+        Self {
+            clock,
+            last: clock.map_or(0.0, |now| now()),
+            timings: Timings::default(),
+        }
+    }
+
+    /// Returns the microseconds elapsed since the previous lap
+    fn lap(&mut self) -> i32 {
+        match self.clock {
+            Some(now) => {
+                let time = now();
+                let elapsed = ((time - self.last) * 1000.0) as i32;
+                self.last = time;
+                elapsed
+            }
+            None => 0,
+        }
+    }
+
+    pub fn tokenize(&mut self) {
+        self.timings.tokenize = self.lap();
+    }
+
+    pub fn fst(&mut self) {
+        self.timings.fst = self.lap();
+    }
+
+    pub fn score(&mut self) {
+        self.timings.score = self.lap();
+    }
+
+    pub fn rank(&mut self) {
+        self.timings.rank = self.lap();
+    }
+
+    /// Returns the recorded timings, or `None` if there is no clock
+    pub fn finish(mut self) -> Option<Timings> {
+        let rest = self.lap();
+        self.clock?;
+        let t = &mut self.timings;
+        t.total = t.tokenize + t.fst + t.score + t.rank + rest;
+        Some(self.timings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// The bits of the fake time, only one test advances it
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    fn fake_clock() -> f64 {
+        f64::from_bits(NOW.load(Ordering::SeqCst))
+    }
+
+    fn advance(ms: f64) {
+        NOW.store((fake_clock() + ms).to_bits(), Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_phase_timer() {
+        advance(10.0);
+        let mut timer = PhaseTimer::start(Some(fake_clock));
+        advance(1.0);
+        timer.tokenize();
+        advance(2.0);
+        timer.fst();
+        // Skipped phases count towards the next one
+        advance(3.0);
+        timer.rank();
+        advance(0.5);
+        let timings = timer.finish().unwrap();
+        assert_eq!(timings.tokenize, 1000);
+        assert_eq!(timings.fst, 2000);
+        assert_eq!(timings.score, 0);
+        assert_eq!(timings.rank, 3000);
+        assert_eq!(timings.total, 6500);
+
+        let mut timer = PhaseTimer::start(None);
+        timer.tokenize();
+        assert!(timer.finish().is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::proto::data::Translation;
+use crate::stemmer;
+use crate::util::{self, Tokenized};
+use std::ops::Range;
+
+/// Splits text into tokens for indexing and searching. Text must be tokenized
+/// the same way when searching as it was when indexing so every tokenizer has a
+/// name which is stored with the index.
+pub trait Tokenizer: Send + Sync {
+    /// Identifies the tokenizer in `IndexData` and search options
+    fn name(&self) -> &'static str;
+
+    /// Normalizes a word (or part of a word) into the form of its token
+    fn fold(&self, word: &str) -> String;
+
+    /// Splits text into tokens with the byte ranges of their source words,
+    /// skipping anything which folds to nothing
+    fn segment(&self, input: &str) -> Vec<(Range<usize>, Tokenized)>;
+
+    /// The stop words used when the index is built without a stop word list
+    fn stop_words(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Reduces a token to its stem so inflections of a word match each other,
+    /// `None` if the tokenizer does not stem
+    fn stem(&self, _token: &str) -> Option<String> {
+        None
+    }
+
+    fn tokenize(&self, input: &str) -> Vec<Tokenized> {
+        self.segment(input)
+            .into_iter()
+            .map(|(_, tokenized)| tokenized)
+            .collect()
+    }
+
+    /// Returns the byte ranges of the tokens in the input, in order, so the range
+    /// for a token position can be found
+    fn token_offsets(&self, input: &str) -> Vec<Range<usize>> {
+        self.segment(input)
+            .into_iter()
+            .map(|(range, _)| range)
+            .collect()
+    }
+}
+
+/// Unicode word segmentation and folding with English stop words and stemming
+pub struct English;
+
+impl Tokenizer for English {
+    fn name(&self) -> &'static str {
+        "english"
+    }
+
+    fn fold(&self, word: &str) -> String {
+        util::fold(word)
+    }
+
+    fn segment(&self, input: &str) -> Vec<(Range<usize>, Tokenized)> {
+        util::segment(input).collect()
+    }
+
+    fn stop_words(&self) -> &'static [&'static str] {
+        util::STOP_WORDS
+    }
+
+    fn stem(&self, token: &str) -> Option<String> {
+        Some(stemmer::stem(token))
+    }
+}
+
+/// Unicode word segmentation and folding without default stop words, a
+/// starting point for languages without a tokenizer of their own (Chinese
+/// ideographs are segmented into single characters)
+pub struct Unicode;
+
+impl Tokenizer for Unicode {
+    fn name(&self) -> &'static str {
+        "unicode"
+    }
+
+    fn fold(&self, word: &str) -> String {
+        util::fold(word)
+    }
+
+    fn segment(&self, input: &str) -> Vec<(Range<usize>, Tokenized)> {
+        util::segment(input).collect()
+    }
+}
+
+pub static ENGLISH: English = English;
+pub static UNICODE: Unicode = Unicode;
+
+/// Returns the tokenizer with the given name
+pub fn tokenizer_by_name(name: &str) -> Option<&'static dyn Tokenizer> {
+    match name {
+        "english" => Some(&ENGLISH),
+        "unicode" => Some(&UNICODE),
+        _ => None,
+    }
+}
+
+/// Returns the tokenizer to index a translation with
+pub fn tokenizer_for(translation: Translation) -> &'static dyn Tokenizer {
+    match translation {
+        Translation::Kjv | Translation::Net | Translation::Bsb | Translation::Total => &ENGLISH,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizers() {
+        for name in &["english", "unicode"] {
+            assert_eq!(tokenizer_by_name(name).unwrap().name(), *name);
+        }
+        assert!(tokenizer_by_name("klingon").is_none());
+
+        assert!(ENGLISH.stop_words().contains(&"THE"));
+        assert!(UNICODE.stop_words().is_empty());
+        assert_eq!(ENGLISH.stem("LOVETH"), Some("LOVE".to_string()));
+        assert_eq!(UNICODE.stem("LOVETH"), None);
+        let tokens: Vec<_> = UNICODE
+            .tokenize("起初，神创造天地。")
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens, vec!["起", "初", "神", "创", "造", "天", "地"]);
+    }
+}
@@ -1,28 +1,46 @@
-use crate::proto::data::{decode_translation_data, Book, Translation, VerseKey, VerseText};
+use crate::proto::data::{
+    decode_translation_data, Book, Translation, TranslationData, VerseKey, VerseText,
+};
 use crate::proto::engine::{
     decode_index_data, IndexData as IndexDataProtoStruct,
-    ReverseIndexEntry as ReverseIndexEntryBytes,
+    ReverseIndexEntry as ReverseIndexEntryBytes, StopWordMode, SynonymGroup,
 };
+use crate::stemmer::stem_key;
+use crate::stop_words::StopWords;
+use crate::synonyms::Synonyms;
+use crate::tokenizer::tokenizer_for;
 use crate::TRANSLATION_COUNT;
 use anyhow::{anyhow, Context, Result};
 use fst::MapBuilder;
-use lazy_static::lazy_static;
 use log::info;
 use regex::Regex;
 use serde::Deserialize;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::io::prelude::*;
 use std::io::{self, BufRead};
 use std::iter::Iterator;
+use std::ops::Range;
 use std::time::Instant;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Deserialize)]
 pub struct Config {
     pub translation_dir: Option<String>,
     pub crawl_data: Option<String>,
+    /// A file of stop words, one per line, replacing the tokenizers' defaults
+    pub stop_words: Option<String>,
+    /// Either "drop" (the default) or "phrase" to index stop words but only
+    /// match them in phrases
+    pub stop_word_mode: Option<String>,
+    /// Whether to index the stems of words so inflections match (the default)
+    pub stemming: Option<bool>,
+    /// A file of synonyms, one group of comma separated words per line
+    pub synonyms: Option<String>,
 }
 
 #[cfg_attr(test, derive(Debug))]
@@ -47,50 +65,57 @@ impl PartialOrd for Tokenized {
 struct VerseStats {
     counts: Vec<usize>,
     highlights: BTreeSet<String>,
+    /// Translation Id => Token positions within the verse (stop words included)
+    positions: Vec<Vec<usize>>,
 }
 
 type TranslationVerses = BTreeMap<Translation, BTreeMap<VerseKey, String>>;
 
-lazy_static! {
-    static ref STOP_WORDS: BTreeSet<&'static str> = {
-        let mut s = BTreeSet::new();
-        s.insert("THE");
-        s.insert("AND");
-        s.insert("OF");
-        s.insert("TO");
-        s.insert("IN");
-        // s.insert("I"); // Do not use "I" as a stop word since it prevents searching for "I AM"
-        s.insert("A");
-        s.insert("IS");
-        s.insert("BE");
-        s.insert("IT");
-        s.insert("ON");
-        s
-    };
+/// The default English stop words. "I" is not a stop word since it would prevent
+/// searching for "I AM".
+pub static STOP_WORDS: &[&str] = &["THE", "AND", "OF", "TO", "IN", "A", "IS", "BE", "IT", "ON"];
+
+/// Folds text for matching: compatibility decomposition (NFKD) splits accented
+/// letters and ligatures, diacritics are dropped, letters are uppercased and
+/// anything which is not alphanumeric (e.g., apostrophes) is removed
+pub fn fold(input: &str) -> String {
+    input
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_uppercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
 }
 
-pub fn tokenize(input: &str) -> Vec<Tokenized> {
+/// Splits input into words (Unicode word boundaries) with their byte ranges,
+/// skipping whitespace, punctuation and anything else which folds to nothing
+pub(crate) fn segment(input: &str) -> impl Iterator<Item = (Range<usize>, Tokenized)> + '_ {
     input
-        .split_whitespace()
-        .map(|s| Tokenized {
-            token: s
-                .chars()
-                // Keeping only alphanumeric characters lets users search without
-                // concern for apostrophes and the like
-                .filter(|c| c.is_ascii_alphanumeric())
-                .collect::<String>()
-                .to_uppercase(),
-            source: s
+        .split_word_bound_indices()
+        .filter_map(|(offset, word)| {
+            let token = fold(word);
+            if token.is_empty() {
+                return None;
+            }
+            let source = word
                 .chars()
-                .enumerate()
-                // Like tokens but with apostophes and commas (except trailing commas)
-                .filter(|(i, c)| {
-                    c.is_ascii_alphanumeric() || *c == '\'' || (*c == ',' && *i != s.len() - 1)
+                // Like tokens but with apostophes and commas (which only appear within words)
+                .filter(|c| {
+                    c.is_alphanumeric() || is_combining_mark(*c) || ['\'', '’', ','].contains(c)
                 })
-                .map(|(_i, c)| c)
-                .collect::<String>(),
+                .collect::<String>();
+            Some((offset..(offset + word.len()), Tokenized { source, token }))
         })
-        .collect()
+}
+
+pub fn tokenize(input: &str) -> Vec<Tokenized> {
+    segment(input).map(|(_, tokenized)| tokenized).collect()
+}
+
+/// Returns the byte ranges of the tokens in the input, in order, so the range
+/// for a token position can be found
+pub fn token_offsets(input: &str) -> Vec<Range<usize>> {
+    segment(input).map(|(range, _)| range).collect()
 }
 
 fn get_config() -> Result<Config> {
@@ -121,7 +146,11 @@ fn read_file_bytes(path: &std::path::PathBuf) -> Result<Vec<u8>> {
 // Stores work-in-progress token counts per verse and translation
 type WipTokenCountsMap = BTreeMap<String, BTreeMap<VerseKey, VerseStats>>;
 
+// Token => Stem
+type TokenStems = BTreeMap<String, String>;
+
 /// Performs initial processing of verses read from disk
+#[allow(clippy::too_many_arguments)]
 fn process_verses(
     translation_key: Translation,
     verses: &[VerseText],
@@ -129,10 +158,13 @@ fn process_verses(
     verse_counts: &mut BTreeMap<VerseKey, u64>,
     highlight_words: &mut BTreeSet<String>,
     wip_token_counts: &mut BTreeMap<String, BTreeMap<VerseKey, VerseStats>>,
+    stop_words: &StopWords,
+    mut stems: Option<&mut TokenStems>,
 ) {
+    let tokenizer = tokenizer_for(translation_key);
     for verse in verses {
         let vkey = verse.key.expect("Missing verse key");
-        let verse_tokens = tokenize(&verse.text);
+        let verse_tokens = tokenizer.tokenize(&verse.text);
         translation_verses
             .entry(translation_key)
             .or_insert_with(BTreeMap::new)
@@ -140,12 +172,23 @@ fn process_verses(
             .or_insert_with(|| verse.text.clone());
         verse_counts.entry(vkey).or_insert(0);
         // Count up tokens
-        for tokenized in verse_tokens
+        for (position, tokenized) in verse_tokens
             .iter()
-            .filter(|t| !STOP_WORDS.contains(t.token.as_str()))
+            // Punctuation-only words (e.g., em dashes) should not count towards positions
+            .filter(|t| !t.token.is_empty())
+            .enumerate()
+            .filter(|(_, t)| !stop_words.is_dropped(&t.token))
         {
             // Save word to get a highlight id later
             highlight_words.insert(tokenized.source.to_uppercase());
+            // Stem each word once
+            if let Some(stems) = stems.as_deref_mut() {
+                if !stems.contains_key(&tokenized.token) {
+                    if let Some(stem) = tokenizer.stem(&tokenized.token) {
+                        stems.insert(tokenized.token.clone(), stem);
+                    }
+                }
+            }
             // Create new stats entry if needed
             let entry = wip_token_counts
                 .entry(tokenized.token.clone())
@@ -154,29 +197,27 @@ fn process_verses(
                 .or_insert_with(|| VerseStats {
                     counts: vec![0; TRANSLATION_COUNT],
                     highlights: BTreeSet::new(),
+                    positions: vec![Vec::new(); TRANSLATION_COUNT],
                 });
             // Increment counts
             entry.counts[translation_key as usize] += 1;
+            // Track positions for phrase matching
+            entry.positions[translation_key as usize].push(position);
             // Track highlights
             entry.highlights.insert(tokenized.source.to_uppercase());
         }
     }
 }
 
-/// Loads translation data from disk and returns the total number of documents
-fn load_translation_data(
-    translation_verses: &mut TranslationVerses,
-    verse_counts: &mut BTreeMap<VerseKey, u64>,
-    highlight_words: &mut BTreeSet<String>,
-    wip_token_counts: &mut WipTokenCountsMap,
-) -> Result<()> {
+/// Loads translation data from disk
+fn load_translation_data() -> Result<Vec<TranslationData>> {
     let config = get_config().context("load_translation_data")?;
     info!("Loading translations from {:?}", config.translation_dir);
     let translation_dir = config
         .translation_dir
         .ok_or_else(|| anyhow!("Environment TRANSLATION_DIR missing"))?;
 
-    let mut total_docs: usize = 0;
+    let mut translations = Vec::new();
 
     for entry in
         fs::read_dir(translation_dir).context("Could not read translation data directory")?
@@ -194,51 +235,37 @@ fn load_translation_data(
             let now = Instant::now();
             let file_bytes = read_file_bytes(&path).expect("Could not read protobuf file");
             let data = decode_translation_data(&*file_bytes).expect("Could not parse protobuf");
-            let translation_key =
-                Translation::from_i32(data.translation).expect("Invalid translation field value");
             info!(
                 "Read {} verses in {}ms",
                 data.verses.len(),
                 now.elapsed().as_millis()
             );
-            total_docs = total_docs.max(data.verses.len());
-            let now = Instant::now();
-            process_verses(
-                translation_key,
-                &data.verses,
-                translation_verses,
-                verse_counts,
-                highlight_words,
-                wip_token_counts,
-            );
-            info!(
-                "Processed {} verses in {}ms",
-                data.verses.len(),
-                now.elapsed().as_millis()
-            );
+            translations.push(data);
         }
     }
 
-    info!("Total verses loaded (all translations): {}", total_docs);
-
-    Ok(())
+    Ok(translations)
 }
 
-/// Build and return a reverse index, fst bytes, and vector of highlight words
+/// Build and return a reverse index, fst bytes, reversed token fst bytes, and
+/// vector of highlight words
 fn build_reverse_index(
     highlight_words: &BTreeSet<String>,
     wip_token_counts: &WipTokenCountsMap,
-) -> (Vec<ReverseIndexEntryBytes>, Vec<u8>, Vec<String>) {
+) -> (Vec<ReverseIndexEntryBytes>, Vec<u8>, Vec<u8>, Vec<String>) {
     let mut build = MapBuilder::memory();
+    let mut reversed_tokens = Vec::with_capacity(wip_token_counts.len());
     let mut reverse_index = Vec::with_capacity(wip_token_counts.len());
     let highlight_words: Vec<_> = highlight_words.iter().cloned().collect();
 
     for (i, (token, entries)) in wip_token_counts.iter().enumerate() {
         build.insert(token.clone(), i as u64).unwrap();
+        reversed_tokens.push((token.chars().rev().collect::<String>(), i as u64));
 
         let mut map_builder = MapBuilder::memory();
         let mut counts_map_data = Vec::new();
         let mut highlights_map_data = Vec::new();
+        let mut positions_map_data = Vec::new();
 
         for (i, (key, vs)) in entries.iter().enumerate() {
             let counts_bytes: Vec<u8> = vs
@@ -266,6 +293,12 @@ fn build_reverse_index(
                         .collect::<Vec<u8>>()
                 })
                 .collect();
+            let positions_bytes: Vec<u8> = vs
+                .positions
+                .iter()
+                .flatten()
+                .flat_map(|p| (*p as u16).to_be_bytes().to_vec())
+                .collect();
 
             let key_bytes = key.to_be_bytes();
             map_builder
@@ -273,6 +306,7 @@ fn build_reverse_index(
                 .expect("Could not insert into reverse index entry map");
             counts_map_data.push(counts_bytes);
             highlights_map_data.push(highlight_index_bytes);
+            positions_map_data.push(positions_bytes);
         }
 
         reverse_index.push(ReverseIndexEntryBytes {
@@ -281,29 +315,80 @@ fn build_reverse_index(
                 .expect("Could not construct counts map bytes"),
             counts_map_data,
             highlights_map_data,
+            positions_map_data,
         });
     }
 
     let fst_bytes = build.into_inner().expect("Could not flush bytes for FST");
     info!("FST compiled: {} bytes", fst_bytes.len());
+
+    // Keys must be inserted in lexicographical order
+    reversed_tokens.sort();
+    let mut build = MapBuilder::memory();
+    for (token, i) in reversed_tokens {
+        build.insert(token, i).unwrap();
+    }
+    let reversed_fst_bytes = build
+        .into_inner()
+        .expect("Could not flush bytes for reversed FST");
+    info!("Reversed FST compiled: {} bytes", reversed_fst_bytes.len());
     info!("Stored {} words for highlighting", highlight_words.len());
 
-    (reverse_index, fst_bytes, highlight_words)
+    (
+        reverse_index,
+        fst_bytes,
+        reversed_fst_bytes,
+        highlight_words,
+    )
 }
 
+/// Builds fst bytes mapping each stem and token (see `stem_key`) to the reverse
+/// index entry of the token
+fn build_stem_fst(stems: &TokenStems, wip_token_counts: &WipTokenCountsMap) -> Result<Vec<u8>> {
+    let rids: HashMap<&str, u64> = wip_token_counts
+        .keys()
+        .enumerate()
+        .map(|(i, token)| (token.as_str(), i as u64))
+        .collect();
+    let mut keys: Vec<_> = stems
+        .iter()
+        .filter_map(|(token, stem)| {
+            rids.get(token.as_str())
+                .map(|rid| (stem_key(stem, token), *rid))
+        })
+        .collect();
+    keys.sort();
+
+    let mut builder = MapBuilder::memory();
+    for (key, rid) in keys {
+        builder
+            .insert(key, rid)
+            .context("Could not insert into stem fst")?;
+    }
+
+    builder
+        .into_inner()
+        .context("Could not build stem fst bytes")
+}
+
+/// Build and return translation verses fst bytes, the verse strings and the
+/// number of tokens in each verse string
 fn build_translation_verses_bytes(
     translation_verses: &TranslationVerses,
-) -> Result<(Vec<u8>, Vec<String>)> {
+) -> Result<(Vec<u8>, Vec<String>, Vec<u32>)> {
     let mut strings = Vec::new();
+    let mut lengths = Vec::new();
     let mut build = MapBuilder::memory();
 
     for (tidx, verses) in translation_verses.iter() {
+        let tokenizer = tokenizer_for(*tidx);
         for (verse_key, text) in verses {
             let key = translation_verses_bytes_key(*tidx as u8, verse_key);
             build
                 .insert(key, strings.len() as u64)
                 .context("Could not insert into translation verses map builder")?;
             strings.push(text.clone());
+            lengths.push(tokenizer.tokenize(text).len() as u32);
         }
     }
 
@@ -311,11 +396,50 @@ fn build_translation_verses_bytes(
         .into_inner()
         .context("Could not build translation verses fst bytes")?;
 
-    Ok((bytes, strings))
+    Ok((bytes, strings, lengths))
 }
 
-/// Loads crawl data from disk
-fn load_crawl_data(verse_rankings: &mut BTreeMap<VerseKey, u64>) -> Result<()> {
+/// Loads the configured stop word list, or the default stop words of every
+/// translation's tokenizer
+fn load_stop_words() -> Result<StopWords> {
+    let config = get_config().context("load_stop_words")?;
+    let mode = match config.stop_word_mode.as_deref() {
+        None | Some("drop") => StopWordMode::Drop,
+        Some("phrase") => StopWordMode::PhraseOnly,
+        Some(mode) => return Err(anyhow!("Unknown stop word mode {:?}", mode)),
+    };
+    let words = match config.stop_words {
+        Some(path) => {
+            info!("Loading stop words from {:?}", path);
+            let text = fs::read_to_string(path).context("Could not read stop words file")?;
+            StopWords::parse_list(&text)
+        }
+        None => (0..TRANSLATION_COUNT)
+            .filter_map(|i| Translation::from_i32(i as i32))
+            .flat_map(|t| tokenizer_for(t).stop_words())
+            .map(|word| word.to_string())
+            .collect(),
+    };
+    Ok(StopWords::new(words, mode))
+}
+
+/// Loads the configured synonym groups, if any
+fn load_synonyms() -> Result<Vec<SynonymGroup>> {
+    let config = get_config().context("load_synonyms")?;
+    let path = match config.synonyms {
+        Some(path) => path,
+        None => return Ok(Vec::new()),
+    };
+    info!("Loading synonyms from {:?}", path);
+    let text = fs::read_to_string(path).context("Could not read synonyms file")?;
+    Ok(Synonyms::parse_list(&text)
+        .into_iter()
+        .map(|words| SynonymGroup { words })
+        .collect())
+}
+
+/// Loads crawl data from disk, returns the key of every visited verse
+fn load_crawl_data() -> Result<Vec<VerseKey>> {
     let config = get_config().context("load_crawl_data")?;
 
     let crawl_data = config
@@ -325,6 +449,7 @@ fn load_crawl_data(verse_rankings: &mut BTreeMap<VerseKey, u64>) -> Result<()> {
     let re = Regex::new(r"^(.+)\s+(\d{1,3}):(\d{1,3})$")
         .context("Could not compile regex for parsing crawl data")?;
 
+    let mut visits = Vec::new();
     // I really did try to avoid this...
     let file = fs::File::open(crawl_data).context("Could not open crawl data file")?;
     for line in io::BufReader::new(file).lines().filter_map(Result::ok) {
@@ -337,20 +462,17 @@ fn load_crawl_data(verse_rankings: &mut BTreeMap<VerseKey, u64>) -> Result<()> {
                     chapter.as_str().parse::<u32>(),
                     verse.as_str().parse::<u32>(),
                 ) {
-                    let key = VerseKey {
+                    visits.push(VerseKey {
                         book: book as i32,
                         chapter,
                         verse,
-                    };
-                    verse_rankings.entry(key).and_modify(|count| {
-                        *count += 1;
                     });
                 }
             }
         }
     }
 
-    Ok(())
+    Ok(visits)
 }
 
 /// Processes crawl data after it is loaded and produce an FST map of verse => count
@@ -371,35 +493,79 @@ fn build_verse_counts_fst(verse_counts: &BTreeMap<VerseKey, u64>) -> Result<Vec<
 
 /// Creates and returns a search index
 pub fn create_index_proto_struct() -> IndexDataProtoStruct {
+    let translations = load_translation_data().expect("Could not load data from disk");
+    let visits = load_crawl_data().expect("Could not load crawl data");
+    let stop_words = load_stop_words().expect("Could not load stop words");
+    let synonyms = load_synonyms().expect("Could not load synonyms");
+    let stemming = get_config()
+        .map(|config| config.stemming.unwrap_or(true))
+        .unwrap_or(true);
+    build_index_proto_struct(&translations, &visits, stop_words, synonyms, stemming)
+}
+
+/// Builds a search index from loaded translations, the popularity of a verse is
+/// the number of times it appears in `visits`
+pub(crate) fn build_index_proto_struct(
+    translations: &[TranslationData],
+    visits: &[VerseKey],
+    stop_words: StopWords,
+    synonyms: Vec<SynonymGroup>,
+    stemming: bool,
+) -> IndexDataProtoStruct {
     let start = Instant::now();
 
     let mut wip_token_counts = BTreeMap::new();
     let mut verse_counts = BTreeMap::new();
     let mut translation_verses: TranslationVerses = BTreeMap::new();
     let mut highlight_words = BTreeSet::new();
+    let mut stems = TokenStems::new();
 
-    load_translation_data(
-        &mut translation_verses,
-        &mut verse_counts,
-        &mut highlight_words,
-        &mut wip_token_counts,
-    )
-    .expect("Could not load data from disk");
+    let mut total_docs: usize = 0;
+    for data in translations {
+        let translation_key =
+            Translation::from_i32(data.translation).expect("Invalid translation field value");
+        total_docs = total_docs.max(data.verses.len());
+        let now = Instant::now();
+        process_verses(
+            translation_key,
+            &data.verses,
+            &mut translation_verses,
+            &mut verse_counts,
+            &mut highlight_words,
+            &mut wip_token_counts,
+            &stop_words,
+            if stemming { Some(&mut stems) } else { None },
+        );
+        info!(
+            "Processed {} verses in {}ms",
+            data.verses.len(),
+            now.elapsed().as_millis()
+        );
+    }
+    info!("Total verses loaded (all translations): {}", total_docs);
 
     let now = Instant::now();
 
-    let (reverse_index_bytes, fst_bytes, highlight_words) =
+    let (reverse_index_bytes, fst_bytes, reversed_fst_bytes, highlight_words) =
         build_reverse_index(&highlight_words, &wip_token_counts);
 
     info!("Indexed data {}ms", now.elapsed().as_millis());
 
-    let (translation_verses_bytes, translation_verses_strings) =
+    let stem_fst_bytes =
+        build_stem_fst(&stems, &wip_token_counts).expect("Could not construct stem fst");
+
+    let (translation_verses_bytes, translation_verses_strings, translation_verse_lengths) =
         build_translation_verses_bytes(&translation_verses)
             .expect("Could not construct translation verses fst map");
 
     let now = Instant::now();
     info!("Building popularlity index");
-    load_crawl_data(&mut verse_counts).expect("Could not load crawl data");
+    // Only count visits to indexed verses
+    for key in visits {
+        verse_counts.entry(*key).and_modify(|count| {
+            *count += 1;
+        });
+    }
     let popularity_bytes =
         build_verse_counts_fst(&verse_counts).expect("Could not construct popularity index");
     info!(
@@ -420,7 +586,17 @@ pub fn create_index_proto_struct() -> IndexDataProtoStruct {
         highlight_words,
         translation_verses: translation_verses_bytes,
         translation_verses_strings,
+        translation_verse_lengths,
         popularity: popularity_bytes,
+        reversed_fst: reversed_fst_bytes,
+        tokenizers: (0..TRANSLATION_COUNT)
+            .filter_map(|i| Translation::from_i32(i as i32))
+            .map(|t| tokenizer_for(t).name().to_string())
+            .collect(),
+        stop_words: stop_words.words().iter().cloned().collect(),
+        stop_word_mode: stop_words.mode() as i32,
+        stem_fst: stem_fst_bytes,
+        synonyms,
     }
 }
 
@@ -497,4 +673,50 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_tokenize_unicode() {
+        let tokens = |input| {
+            tokenize(input)
+                .into_iter()
+                .map(|t| (t.source, t.token))
+                .collect::<Vec<_>>()
+        };
+        let pair = |source: &str, token: &str| (source.to_string(), token.to_string());
+        assert_eq!(
+            tokens("Naïve Élie’s ﬁne straße"),
+            vec![
+                pair("Naïve", "NAIVE"),
+                pair("Élie’s", "ELIES"),
+                pair("ﬁne", "FINE"),
+                pair("straße", "STRASSE"),
+            ]
+        );
+        assert_eq!(
+            tokens("well-pleased, 1,000 Ἰησοῦς"),
+            vec![
+                pair("well", "WELL"),
+                pair("pleased", "PLEASED"),
+                pair("1,000", "1000"),
+                pair("Ἰησοῦς", "ΙΗΣΟΥΣ"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_offsets() {
+        let text = "“It's all — good,” he said";
+        let words: Vec<_> = token_offsets(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(words, vec!["It's", "all", "good", "he", "said"]);
+        assert_eq!(
+            words.len(),
+            tokenize(text)
+                .iter()
+                .filter(|t| !t.token.is_empty())
+                .count()
+        );
+    }
 }
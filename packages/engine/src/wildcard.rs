@@ -0,0 +1,172 @@
+use fst::Automaton;
+
+/// The longest pattern which can be matched, one bit of state per byte
+static MAX_PATTERN_LEN: usize = 127;
+
+/// Returns true if the token contains wildcards
+pub fn is_wildcard(token: &str) -> bool {
+    token.contains(&['*', '?'][..])
+}
+
+/// Returns the literal part of a suffix pattern (e.g., "EOUSNESS" for
+/// "*EOUSNESS"), suffix patterns can be searched as a prefix of reversed tokens
+pub fn suffix_literal(token: &str) -> Option<&str> {
+    token
+        .strip_prefix('*')
+        .map(|rest| rest.trim_start_matches('*'))
+        .filter(|rest| !rest.is_empty() && !is_wildcard(rest))
+}
+
+/// An automaton matching tokens against a pattern where `*` matches any number
+/// of characters and `?` matches exactly one character
+pub struct Wildcard {
+    pattern: Vec<u8>,
+}
+
+impl Wildcard {
+    /// Returns `None` if the pattern is too long to be matched
+    pub fn new(pattern: &str) -> Option<Self> {
+        if pattern.len() > MAX_PATTERN_LEN {
+            return None;
+        }
+        Some(Self {
+            pattern: pattern.bytes().collect(),
+        })
+    }
+
+    /// Adds the positions following any `*` to the state since `*` may match nothing
+    fn closure(&self, mut state: u128) -> u128 {
+        for (i, b) in self.pattern.iter().enumerate() {
+            if *b == b'*' && state & (1 << i) != 0 {
+                state |= 1 << (i + 1);
+            }
+        }
+        state
+    }
+}
+
+/// Returns the number of continuation bytes following a UTF-8 lead byte
+fn continuation_bytes(lead: u8) -> u8 {
+    match lead {
+        0xF0..=0xFF => 3,
+        0xE0..=0xEF => 2,
+        0xC0..=0xDF => 1,
+        _ => 0,
+    }
+}
+
+impl Automaton for Wildcard {
+    /// Bit `i` of the first set is set when the first `i` bytes of the pattern
+    /// have been matched. Wildcards match whole characters so positions reached
+    /// by a `*` or `?` within a multibyte character wait in the second set until
+    /// the last of the remaining (third) continuation bytes is read.
+    type State = (u128, u128, u8);
+
+    fn start(&self) -> Self::State {
+        (self.closure(1), 0, 0)
+    }
+
+    fn is_match(&self, (state, _, pending): &Self::State) -> bool {
+        *pending == 0 && state & (1 << self.pattern.len()) != 0
+    }
+
+    fn can_match(&self, (state, waiting, _): &Self::State) -> bool {
+        *state != 0 || *waiting != 0
+    }
+
+    fn accept(&self, (state, waiting, pending): &Self::State, byte: u8) -> Self::State {
+        let lead = *pending == 0;
+        let pending = if lead {
+            continuation_bytes(byte)
+        } else {
+            pending - 1
+        };
+        let mut next = 0;
+        let mut waiting = *waiting;
+        for (i, b) in self.pattern.iter().enumerate() {
+            if state & (1 << i) == 0 {
+                continue;
+            }
+            // Wildcards only start on a character's first byte
+            let matched = match *b {
+                b'*' if lead => 1 << i,
+                b'?' if lead => 1 << (i + 1),
+                b if b == byte => {
+                    next |= 1 << (i + 1);
+                    continue;
+                }
+                _ => continue,
+            };
+            if pending > 0 {
+                waiting |= matched;
+            } else {
+                next |= matched;
+            }
+        }
+        if pending == 0 {
+            next |= waiting;
+            waiting = 0;
+        }
+        (self.closure(next), waiting, pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fst::{IntoStreamer, Set};
+
+    fn search(pattern: &str) -> Vec<String> {
+        let set = Set::from_iter(vec![
+            "BAPTISE",
+            "BAPTIZE",
+            "BAPTIZED",
+            "RIGHTEOUSNESS",
+            "UNRIGHTEOUSNESS",
+            "ΛΟΓΟΣ",
+            "ΛΟΓΟΥ",
+            "ΛΟΓΩΝ",
+            "道",
+        ])
+        .unwrap();
+        set.search(Wildcard::new(pattern).unwrap())
+            .into_stream()
+            .into_strs()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_wildcard() {
+        assert_eq!(search("BAPTI?E"), vec!["BAPTISE", "BAPTIZE"]);
+        assert_eq!(search("BAPTIZ*"), vec!["BAPTIZE", "BAPTIZED"]);
+        assert_eq!(
+            search("*EOUSNESS"),
+            vec!["RIGHTEOUSNESS", "UNRIGHTEOUSNESS"]
+        );
+        assert_eq!(search("B*T*D"), vec!["BAPTIZED"]);
+        assert!(search("BAPTI?").is_empty());
+
+        // Wildcards match whole characters, not bytes
+        assert_eq!(search("ΛΟΓΟ?"), vec!["ΛΟΓΟΣ", "ΛΟΓΟΥ"]);
+        assert_eq!(search("ΛΟΓ??"), vec!["ΛΟΓΟΣ", "ΛΟΓΟΥ", "ΛΟΓΩΝ"]);
+        assert!(search("ΛΟΓ?").is_empty());
+        assert!(search("ΛΟΓΟ??").is_empty());
+        assert_eq!(search("*Ω*"), vec!["ΛΟΓΩΝ"]);
+        assert_eq!(search("?"), vec!["道"]);
+        assert_eq!(search("*Σ"), vec!["ΛΟΓΟΣ"]);
+
+        // Patterns longer than the state can track are rejected, not truncated
+        let pattern = format!("{}*", "B".repeat(MAX_PATTERN_LEN - 1));
+        assert!(Wildcard::new(&pattern).is_some());
+        assert!(Wildcard::new(&format!("{}*", pattern)).is_none());
+    }
+
+    #[test]
+    fn test_suffix_literal() {
+        assert_eq!(suffix_literal("*EOUSNESS"), Some("EOUSNESS"));
+        assert_eq!(suffix_literal("**NESS"), Some("NESS"));
+        assert_eq!(suffix_literal("*EOUS?ESS"), None);
+        assert_eq!(suffix_literal("RIGHT*"), None);
+        assert_eq!(suffix_literal("*"), None);
+    }
+}
@@ -15,6 +15,16 @@ void bridge_init(const uint8_t *raw_data, uintptr_t len);
 
 IbRustBuffer bridge_search(const char *bytes);
 
+IbRustBuffer bridge_search_with_options(const char *bytes, const uint8_t *raw_options, uintptr_t len);
+
+IbRustBuffer bridge_get_verse(int32_t book, uint32_t chapter, uint32_t verse);
+
+IbRustBuffer bridge_get_chapter(int32_t book, uint32_t chapter);
+
+IbRustBuffer bridge_get_range(int32_t start_book, uint32_t start_chapter, uint32_t start_verse, int32_t end_book, uint32_t end_chapter, uint32_t end_verse);
+
+IbRustBuffer bridge_complete(const char *bytes, uint32_t n);
+
 void bridge_search_free(IbRustBuffer buf);
 
 #endif
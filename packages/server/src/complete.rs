@@ -0,0 +1,25 @@
+use engine::proto::service::Completions;
+use engine::VersearchIndex;
+use log::info;
+use std::sync::Arc;
+use warp::{Filter, Rejection};
+
+#[derive(serde::Deserialize)]
+struct Query {
+  // Partially typed query text, the last word is completed
+  q: String,
+  // The maximum number of completions
+  limit: Option<u32>,
+}
+
+// GET /complete?q=:text&limit=:n, e.g., /complete?q=righ
+pub fn complete_filter(
+  index: Arc<VersearchIndex>,
+) -> impl Filter<Extract = (Completions,), Error = Rejection> + Clone {
+  warp::path!("complete")
+    .and(warp::filters::query::query::<Query>())
+    .map(move |query: Query| {
+      info!(r#"Completing """{}""""#, query.q);
+      Completions::from(index.complete(&query.q, query.limit.unwrap_or(0) as usize))
+    })
+}
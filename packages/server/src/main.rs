@@ -1,7 +1,9 @@
+mod complete;
 mod response;
 mod search;
+mod verses;
 
-use engine::proto::service::Response as ServiceResponse;
+use engine::proto::service::{Completions, Response as ServiceResponse};
 use engine::util::get_or_create_index_proto_struct;
 use engine::VersearchIndex;
 use log::info;
@@ -21,13 +23,27 @@ async fn main() {
 
     info!("Starting server...");
 
+    // Verse lookups are matched by path, searches by query string alone
+    let filter = verses::verses_filter(Arc::clone(&index))
+        .or(search::search_filter(Arc::clone(&index)))
+        .unify();
     let route_proto = warp::header::exact_ignore_case("accept", "application/protobuf")
-        .and(search::search_filter(Arc::clone(&index)))
+        .and(filter.clone())
         .map(|res: ServiceResponse| response::protobuf(&res));
-    let route_json = search::search_filter(Arc::clone(&index))
-        .map(|res: ServiceResponse| warp::reply::json(&res));
+    let route_json = filter.map(|res: ServiceResponse| warp::reply::json(&res));
 
-    let route = route_proto
+    // Completions are matched by path so they must come before searches
+    let complete = complete::complete_filter(Arc::clone(&index));
+    let complete_proto = warp::header::exact_ignore_case("accept", "application/protobuf")
+        .and(complete.clone())
+        .map(|res: Completions| response::protobuf(&res));
+    let complete_json = complete.map(|res: Completions| warp::reply::json(&res));
+
+    let route = complete_proto
+        .or(complete_json)
+        .or(verses::invalid_range_filter())
+        .or(search::invalid_search_filter())
+        .or(route_proto)
         .or(route_json)
         .with(warp::cors().allow_any_origin());
     warp::serve(route).run(([0, 0, 0, 0], 8081)).await
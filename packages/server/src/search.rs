@@ -1,19 +1,127 @@
-use engine::proto::service::Response as ServiceResponse;
-use engine::VersearchIndex;
+use engine::proto::data::Translation;
+use engine::proto::service::{search_options, Response as ServiceResponse};
+use engine::reference::{parse_book, parse_range};
+use engine::{SearchOptions, VersearchIndex};
 use log::info;
 use std::sync::Arc;
+use warp::http::StatusCode;
 use warp::{Filter, Rejection};
 
 #[derive(serde::Deserialize)]
 struct Query {
   q: String,
+  // Comma separated book names, e.g., "Matthew,Mark,Luke,John"
+  books: Option<String>,
+  // Either "old" or "new"
+  testament: Option<String>,
+  // Comma separated ranges, e.g., "Romans-Jude,John 3-5"
+  ranges: Option<String>,
+  // Comma separated translations, e.g., "kjv,net"
+  translations: Option<String>,
+  offset: Option<u32>,
+  limit: Option<u32>,
+  // Either "default", "as-you-type" or "suggest"
+  mode: Option<String>,
+  // The number of verses before and after each result to include
+  context: Option<u32>,
+  // Either "bytes" or "utf16"
+  offsets: Option<String>,
+  // The approximate number of characters in snippets
+  snippet_length: Option<u32>,
+  // The tokenizer for the query, e.g., "english"
+  tokenizer: Option<String>,
+  // Comma separated ranking rules, e.g., "words,typo,proximity,popularity"
+  ranking: Option<String>,
+}
+
+impl Query {
+  /// Returns `None` if any book, range or translation cannot be parsed, dropping
+  /// it would widen the search instead of narrowing it
+  fn options(&self) -> Option<SearchOptions> {
+    let split = |s: &Option<String>| {
+      s.as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+    };
+    let testament = match self.testament.as_deref().map(|t| t.to_lowercase()) {
+      Some(ref t) if t == "old" => search_options::Testament::Old,
+      Some(ref t) if t == "new" => search_options::Testament::New,
+      _ => search_options::Testament::All,
+    };
+    let offsets = match self.offsets.as_deref().map(|o| o.to_lowercase()) {
+      Some(ref o) if o == "utf16" => search_options::Offsets::Utf16,
+      _ => search_options::Offsets::Bytes,
+    };
+    let mode = match self.mode.as_deref().map(|m| m.to_lowercase()) {
+      Some(ref m) if m == "as-you-type" => search_options::Mode::AsYouType,
+      Some(ref m) if m == "suggest" => search_options::Mode::Suggest,
+      _ => search_options::Mode::Default,
+    };
+
+    Some(SearchOptions {
+      books: split(&self.books)
+        .iter()
+        .map(|b| parse_book(b).map(|b| b as i32))
+        .collect::<Option<_>>()?,
+      testament: testament as i32,
+      ranges: split(&self.ranges)
+        .iter()
+        .map(|r| {
+          parse_range(r).map(|(start, end)| search_options::Range {
+            start: Some(start),
+            end: Some(end),
+          })
+        })
+        .collect::<Option<_>>()?,
+      translations: split(&self.translations)
+        .iter()
+        .map(|t| {
+          Translation::from_string(&t.to_uppercase())
+            .ok()
+            .map(|t| t as i32)
+        })
+        .collect::<Option<_>>()?,
+      offset: self.offset.unwrap_or(0),
+      limit: self.limit.unwrap_or(0),
+      mode: mode as i32,
+      context: self.context.unwrap_or(0),
+      offsets: offsets as i32,
+      snippet_length: self.snippet_length.unwrap_or(0),
+      tokenizer: self.tokenizer.as_deref().unwrap_or("").to_lowercase(),
+      ranking_rules: split(&self.ranking)
+        .iter()
+        .map(|r| r.to_lowercase())
+        .collect(),
+    })
+  }
 }
 
 pub fn search_filter(
   index: Arc<VersearchIndex>,
 ) -> impl Filter<Extract = (ServiceResponse,), Error = Rejection> + Clone {
-  warp::filters::query::query::<Query>().map(move |Query { q }| {
-    info!(r#"Searching for """{}""""#, q);
-    index.search(&q)
+  warp::filters::query::query::<Query>().and_then(move |query: Query| {
+    let index = Arc::clone(&index);
+    async move {
+      info!(r#"Searching for """{}""""#, query.q);
+      let options = query.options().ok_or_else(warp::reject::not_found)?;
+      Ok::<_, Rejection>(index.search_with_options(&query.q, &options))
+    }
+  })
+}
+
+// GET ?q=:query with a book, range or translation which cannot be parsed,
+// answered with 400 Bad Request rather than searching every verse
+pub fn invalid_search_filter() -> impl Filter<Extract = (StatusCode,), Error = Rejection> + Clone {
+  warp::filters::query::query::<Query>().and_then(|query: Query| async move {
+    match query.options() {
+      Some(_) => Err(warp::reject::not_found()),
+      None => {
+        info!(r#"Invalid filters for """{}""""#, query.q);
+        Ok(StatusCode::BAD_REQUEST)
+      }
+    }
   })
 }
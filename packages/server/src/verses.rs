@@ -0,0 +1,96 @@
+use engine::proto::data::VerseKey;
+use engine::proto::service::Response as ServiceResponse;
+use engine::reference::{parse_book, parse_range};
+use engine::VersearchIndex;
+use log::info;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+#[derive(serde::Deserialize)]
+struct RangeQuery {
+  // A range of verses, e.g., "Romans 8:28-39"
+  q: String,
+}
+
+// GET /verse/:book/:chapter/:verse, e.g., /verse/1john/4/8
+fn verse_filter(
+  index: Arc<VersearchIndex>,
+) -> impl Filter<Extract = (ServiceResponse,), Error = Rejection> + Clone {
+  warp::path!("verse" / String / u32 / u32).and_then(move |book: String, chapter, verse| {
+    let index = Arc::clone(&index);
+    async move {
+      info!("Getting verse {} {}:{}", book, chapter, verse);
+      let book = parse_book(&book).ok_or_else(warp::reject::not_found)?;
+      let key = VerseKey {
+        book: book as i32,
+        chapter,
+        verse,
+      };
+      index
+        .get_verse(&key)
+        .map(|result| ServiceResponse::from(vec![result]))
+        .ok_or_else(warp::reject::not_found)
+    }
+  })
+}
+
+// GET /chapter/:book/:chapter, e.g., /chapter/john/3
+fn chapter_filter(
+  index: Arc<VersearchIndex>,
+) -> impl Filter<Extract = (ServiceResponse,), Error = Rejection> + Clone {
+  warp::path!("chapter" / String / u32).and_then(move |book: String, chapter| {
+    let index = Arc::clone(&index);
+    async move {
+      info!("Getting chapter {} {}", book, chapter);
+      let book = parse_book(&book).ok_or_else(warp::reject::not_found)?;
+      let results = index.get_chapter(book, chapter);
+      if results.is_empty() {
+        return Err(warp::reject::not_found());
+      }
+      Ok(ServiceResponse::from(results))
+    }
+  })
+}
+
+// GET /range?q=:range, e.g., /range?q=Romans%208:28-39
+fn range_filter(
+  index: Arc<VersearchIndex>,
+) -> impl Filter<Extract = (ServiceResponse,), Error = Rejection> + Clone {
+  warp::path!("range")
+    .and(warp::filters::query::query::<RangeQuery>())
+    .and_then(move |query: RangeQuery| {
+      let index = Arc::clone(&index);
+      async move {
+        info!("Getting range {}", query.q);
+        let (start, end) = parse_range(&query.q).ok_or_else(warp::reject::not_found)?;
+        Ok::<_, Rejection>(index.get_range(&start, &end).into())
+      }
+    })
+}
+
+// GET /range?q=:range where the range cannot be parsed, answered with 400 Bad
+// Request before searches would treat the query as text
+pub fn invalid_range_filter() -> impl Filter<Extract = (StatusCode,), Error = Rejection> + Clone {
+  warp::path!("range")
+    .and(warp::filters::query::query::<RangeQuery>())
+    .and_then(|query: RangeQuery| async move {
+      match parse_range(&query.q) {
+        Some(_) => Err(warp::reject::not_found()),
+        None => {
+          info!("Invalid range {}", query.q);
+          Ok(StatusCode::BAD_REQUEST)
+        }
+      }
+    })
+}
+
+pub fn verses_filter(
+  index: Arc<VersearchIndex>,
+) -> impl Filter<Extract = (ServiceResponse,), Error = Rejection> + Clone {
+  verse_filter(Arc::clone(&index))
+    .or(chapter_filter(Arc::clone(&index)))
+    .unify()
+    .or(range_filter(index))
+    .unify()
+}